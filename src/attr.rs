@@ -0,0 +1,173 @@
+//! Lookups of git attributes, as set by the `.gitattributes` files of a commit
+//!
+//! git2 only binds `git_attr_get`, which reads the `.gitattributes` files of
+//! the working tree and of the index: bare repositories have neither, and the
+//! branches other than `HEAD` may have different attributes. So we call
+//! `git_attr_get_ext` ourselves, with the commit being rendered.
+
+use std::{
+  ffi::CString,
+  os::{
+    raw::{c_char, c_int, c_uint},
+    unix::ffi::OsStrExt,
+  },
+  path::Path,
+  ptr,
+};
+use git2::Oid;
+use libgit2_sys as raw;
+
+/// Reads attributes from the `.gitattributes` files of a given commit, see
+/// `GIT_ATTR_CHECK_INCLUDE_COMMIT` in `git2/attr.h`
+const GIT_ATTR_CHECK_INCLUDE_COMMIT: c_uint = 1 << 4;
+const GIT_ATTR_OPTIONS_VERSION:      c_uint = 1;
+
+/// `git_attr_options`, which libgit2-sys doesn't declare
+#[repr(C)]
+struct GitAttrOptions {
+  version:        c_uint,
+  flags:          c_uint,
+  commit_id:      *mut raw::git_oid,
+  attr_commit_id: raw::git_oid,
+}
+
+extern "C" {
+  fn git_attr_get_ext(
+    value_out: *mut *const c_char,
+    repo: *mut raw::git_repository,
+    opts: *mut GitAttrOptions,
+    path: *const c_char,
+    name: *const c_char,
+  ) -> c_int;
+}
+
+/// The attributes of the files of a commit
+pub struct Attributes {
+  /// The repository, opened as if it were bare: so that the attributes of the
+  /// working tree and of the index don't leak into other commits
+  repo:   *mut raw::git_repository,
+  commit: raw::git_oid,
+}
+
+impl Attributes {
+  /// Opens the repository whose git directory is `path`, to look up the
+  /// attributes of the files of `commit`
+  pub fn open(path: &Path, commit: Oid) -> Option<Self> {
+    raw::init();
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut repo = ptr::null_mut();
+    let result = unsafe {
+      raw::git_repository_open_ext(
+        &mut repo,
+        path.as_ptr(),
+        raw::GIT_REPOSITORY_OPEN_BARE as c_uint,
+        ptr::null(),
+      )
+    };
+    if result < 0 {
+      return None;
+    }
+
+    let mut id = [0; raw::GIT_OID_MAX_SIZE];
+    id.copy_from_slice(commit.as_bytes());
+    Some(Self { repo, commit: raw::git_oid { id } })
+  }
+
+  /// Checks if the attribute `name` is set for `path`, relative to the root
+  /// of the repository
+  pub fn is_set(&self, path: &Path, name: &str) -> bool {
+    let (Ok(path), Ok(name)) = (
+      CString::new(path.as_os_str().as_bytes()),
+      CString::new(name),
+    ) else {
+      return false;
+    };
+
+    let mut opts = GitAttrOptions {
+      version:        GIT_ATTR_OPTIONS_VERSION,
+      flags:          GIT_ATTR_CHECK_INCLUDE_COMMIT | raw::GIT_ATTR_CHECK_NO_SYSTEM,
+      commit_id:      ptr::null_mut(),
+      attr_commit_id: raw::git_oid { id: self.commit.id },
+    };
+
+    let mut value = ptr::null();
+    unsafe {
+      let result = git_attr_get_ext(
+        &mut value,
+        self.repo,
+        &mut opts,
+        path.as_ptr(),
+        name.as_ptr(),
+      );
+      result == 0 && raw::git_attr_value(value) == raw::GIT_ATTR_VALUE_TRUE
+    }
+  }
+}
+
+impl Drop for Attributes {
+  fn drop(&mut self) {
+    unsafe { raw::git_repository_free(self.repo) }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{env, fs};
+  use git2::{Repository, Signature};
+
+  /// Commits `files` to `repo` without touching its working tree, if any
+  fn commit(repo: &Repository, files: &[(&str, &[u8])]) -> Oid {
+    let mut tree = repo.treebuilder(None).unwrap();
+    for (name, content) in files {
+      let blob = repo.blob(content).unwrap();
+      tree.insert(name, blob, 0o100644).unwrap();
+    }
+    let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+
+    let sig = Signature::now("Jane Doe", "jane@example.com").unwrap();
+    repo.commit(None, &sig, &sig, "Commit", &tree, &[]).unwrap()
+  }
+
+  #[test]
+  fn attributes_of_bare_repos() {
+    let path = env::temp_dir().join(format!("yagit-attr-bare-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    let repo = Repository::init_bare(&path).unwrap();
+
+    let hidden = commit(&repo, &[
+      (".gitattributes", b"secret.txt export-ignore\n"),
+      ("secret.txt",     b"hunter2\n"),
+      ("public.txt",     b"hello\n"),
+    ]);
+    let shown = commit(&repo, &[
+      ("secret.txt", b"hunter2\n"),
+    ]);
+
+    let attrs = Attributes::open(repo.path(), hidden).unwrap();
+    assert!(attrs.is_set(Path::new("secret.txt"), "export-ignore"));
+    assert!(!attrs.is_set(Path::new("public.txt"), "export-ignore"));
+
+    // the attributes of other commits don't leak
+    let attrs = Attributes::open(repo.path(), shown).unwrap();
+    assert!(!attrs.is_set(Path::new("secret.txt"), "export-ignore"));
+
+    let _ = fs::remove_dir_all(&path);
+  }
+
+  #[test]
+  fn working_tree_attributes_are_ignored() {
+    let path = env::temp_dir().join(format!("yagit-attr-workdir-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    let repo = Repository::init(&path).unwrap();
+    fs::write(path.join(".gitattributes"), "secret.txt export-ignore\n").unwrap();
+
+    let id = commit(&repo, &[("secret.txt", b"hunter2\n")]);
+
+    let attrs = Attributes::open(repo.path(), id).unwrap();
+    assert!(!attrs.is_set(Path::new("secret.txt"), "export-ignore"));
+
+    let _ = fs::remove_dir_all(&path);
+  }
+}
@@ -1,17 +1,29 @@
-use std::{env, ops::BitOrAssign};
+use std::ops::BitOrAssign;
 
 const RENDER_BATCH_CMD: &str = "render-batch";
 const RENDER_CMD:       &str = "render";
 const INIT_CMD:         &str = "init";
-const DELETE_CMD:           &str = "delete";
+const DELETE_CMD:       &str = "delete";
 
 const FULL_BUILD_FLAG: &str = "--full-build";
 const PRIVATE_FLAG:    &str = "--private";
+const GZIP_FLAG:       &str = "--gzip";
+const BRANCH_FLAG:     &str = "--branch";
+const JOBS_FLAG:       &str = "--jobs";
+const QUIET_FLAG:      &str = "--quiet";
+const VERBOSE_FLAG:    &str = "--verbose";
+const DRY_RUN_FLAG:    &str = "--dry-run";
+const BARE_FLAG:       &str = "--bare";
+const PURGE_FLAG:      &str = "--purge";
+const MANIFEST_FLAG:   &str = "--manifest";
 
 #[derive(Clone, Debug)]
 pub struct Cmd {
   pub sub_cmd: SubCmd,
   pub flags:   Flags,
+  pub branch:  Option<String>,
+  /// The number of threads pages are rendered on
+  pub jobs:    Option<usize>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -38,8 +50,14 @@ pub enum SubCmd {
 }
 
 impl Cmd {
-  pub fn parse(args: &mut env::Args, program_name: &str) -> Result<Self, ()> {
+  /// Parses the command line arguments following the program name
+  pub fn parse<I>(args: &mut I, program_name: &str) -> Result<Self, ()>
+  where
+    I: Iterator<Item = String>,
+  {
     let mut flags = Flags::EMPTY;
+    let mut branch = None;
+    let mut jobs = None;
     let tag = loop {
       match args.next() {
         Some(arg) if arg == RENDER_BATCH_CMD => break CmdTag::RenderBatch,
@@ -53,6 +71,51 @@ impl Cmd {
         Some(arg) if arg == PRIVATE_FLAG => {
           flags |= Flags::PRIVATE;
         }
+        Some(arg) if arg == GZIP_FLAG => {
+          flags |= Flags::GZIP;
+        }
+        Some(arg) if arg == QUIET_FLAG => {
+          flags |= Flags::QUIET;
+        }
+        Some(arg) if arg == VERBOSE_FLAG => {
+          flags |= Flags::VERBOSE;
+        }
+        Some(arg) if arg == DRY_RUN_FLAG => {
+          flags |= Flags::DRY_RUN;
+        }
+        Some(arg) if arg == BARE_FLAG => {
+          flags |= Flags::BARE;
+        }
+        Some(arg) if arg == PURGE_FLAG => {
+          flags |= Flags::PURGE;
+        }
+        Some(arg) if arg == MANIFEST_FLAG => {
+          flags |= Flags::MANIFEST;
+        }
+        Some(arg) if arg == BRANCH_FLAG => {
+          if let Some(name) = args.next() {
+            branch = Some(name);
+          } else {
+            errorln!("No branch name provided to {BRANCH_FLAG}");
+            usage(program_name, None);
+            return Err(());
+          }
+        }
+        Some(arg) if arg == JOBS_FLAG => {
+          match args.next().map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if n > 0 => jobs = Some(n),
+            Some(_) => {
+              errorln!("{JOBS_FLAG} should be given a positive number of jobs");
+              usage(program_name, None);
+              return Err(());
+            }
+            None => {
+              errorln!("No number of jobs provided to {JOBS_FLAG}");
+              usage(program_name, None);
+              return Err(());
+            }
+          }
+        }
 
         Some(arg) if arg.starts_with("--") => {
           errorln!("Unknown flag {arg:?}");
@@ -84,6 +147,7 @@ impl Cmd {
           usage(program_name, Some(tag));
           return Err(());
         };
+        validate_repo_name(&repo_name)?;
 
         SubCmd::Render { repo_name, }
       }
@@ -95,6 +159,7 @@ impl Cmd {
           usage(program_name, Some(tag));
           return Err(());
         };
+        validate_repo_name(&repo_name)?;
 
         let description = if let Some(dsc) = args.next() {
           dsc
@@ -114,30 +179,101 @@ impl Cmd {
           usage(program_name, Some(tag));
           return Err(());
         };
+        validate_repo_name(&repo_name)?;
 
         SubCmd::Delete { repo_name, }
       }
     };
 
+    // init doesn't touch the output directory and delete is interactive, so a
+    // dry-run wouldn't make much sense for them
+    if flags.dry_run() && matches!(tag, CmdTag::Init | CmdTag::Delete) {
+      errorln!("{DRY_RUN_FLAG} is only supported by {RENDER_CMD} and {RENDER_BATCH_CMD}");
+      usage(program_name, Some(tag));
+      return Err(());
+    }
+
+    if jobs.is_some() && matches!(tag, CmdTag::Init | CmdTag::Delete) {
+      errorln!("{JOBS_FLAG} is only supported by {RENDER_CMD} and {RENDER_BATCH_CMD}");
+      usage(program_name, Some(tag));
+      return Err(());
+    }
+
+    if flags.manifest() && matches!(tag, CmdTag::Init | CmdTag::Delete) {
+      errorln!("{MANIFEST_FLAG} is only supported by {RENDER_CMD} and {RENDER_BATCH_CMD}");
+      usage(program_name, Some(tag));
+      return Err(());
+    }
+
+    if flags.bare() && tag != CmdTag::Init {
+      errorln!("{BARE_FLAG} is only supported by {INIT_CMD}");
+      usage(program_name, Some(tag));
+      return Err(());
+    }
+
+    if flags.purge() && tag != CmdTag::Delete {
+      errorln!("{PURGE_FLAG} is only supported by {DELETE_CMD}");
+      usage(program_name, Some(tag));
+      return Err(());
+    }
+
     if args.next().is_some() {
       warnln!("Additional command line arguments provided. Ignoring trailing arguments...");
       usage(program_name, Some(tag));
     }
 
-    Ok(Self { sub_cmd, flags, })
+    Ok(Self { sub_cmd, flags, branch, jobs, })
   }
 }
 
+/// Checks that `name` refers to a directory right inside the repository store
+///
+/// `render` is run from Git hooks with names derived from the repository, so
+/// a maliciously-named repository could otherwise make us write outside of
+/// the output directory.
+fn validate_repo_name(name: &str) -> Result<(), ()> {
+  if name.is_empty() {
+    errorln!("Repository names should not be empty");
+    return Err(());
+  }
+
+  if name.starts_with('.') {
+    errorln!("Invalid repository name {name:?}: repository names should not start with a dot");
+    return Err(());
+  }
+
+  if name.contains(['/', '\\', '\0']) || name.contains("..") {
+    errorln!("Invalid repository name {name:?}: repository names should not contain slashes, \"..\" or NUL characters");
+    return Err(());
+  }
+
+  Ok(())
+}
+
 #[derive(Clone, Copy, Debug)]
-pub struct Flags(u8);
+pub struct Flags(u16);
 
 impl Flags {
-  const FULL_BUILD_RAW: u8 = 0b00000001;
-  const PRIVATE_RAW:    u8 = 0b00000010;
+  const FULL_BUILD_RAW: u16 = 0b000000001;
+  const PRIVATE_RAW:    u16 = 0b000000010;
+  const GZIP_RAW:       u16 = 0b000000100;
+  const QUIET_RAW:      u16 = 0b000001000;
+  const VERBOSE_RAW:    u16 = 0b000010000;
+  const DRY_RUN_RAW:    u16 = 0b000100000;
+  const BARE_RAW:       u16 = 0b001000000;
+  const PURGE_RAW:      u16 = 0b010000000;
+  const MANIFEST_RAW:   u16 = 0b100000000;
 
   pub const EMPTY:      Self = Self(0);
   pub const FULL_BUILD: Self = Self(Self ::FULL_BUILD_RAW);
   pub const PRIVATE:    Self = Self(Self ::PRIVATE_RAW);
+  pub const GZIP:       Self = Self(Self ::GZIP_RAW);
+  pub const QUIET:      Self = Self(Self ::QUIET_RAW);
+  pub const VERBOSE:    Self = Self(Self ::VERBOSE_RAW);
+  pub const DRY_RUN:    Self = Self(Self ::DRY_RUN_RAW);
+  pub const BARE:       Self = Self(Self ::BARE_RAW);
+  pub const PURGE:      Self = Self(Self ::PURGE_RAW);
+  pub const MANIFEST:   Self = Self(Self ::MANIFEST_RAW);
 
   pub fn full_build(self) -> bool {
     self.0 & Self::FULL_BUILD_RAW != 0
@@ -146,6 +282,43 @@ impl Flags {
   pub fn private(self) -> bool {
     self.0 & Self::PRIVATE_RAW != 0
   }
+
+  pub fn gzip(self) -> bool {
+    self.0 & Self::GZIP_RAW != 0
+  }
+
+  pub fn quiet(self) -> bool {
+    self.0 & Self::QUIET_RAW != 0
+  }
+
+  pub fn verbose(self) -> bool {
+    self.0 & Self::VERBOSE_RAW != 0
+  }
+
+  pub fn dry_run(self) -> bool {
+    self.0 & Self::DRY_RUN_RAW != 0
+  }
+
+  pub fn bare(self) -> bool {
+    self.0 & Self::BARE_RAW != 0
+  }
+
+  pub fn purge(self) -> bool {
+    self.0 & Self::PURGE_RAW != 0
+  }
+
+  pub fn manifest(self) -> bool {
+    self.0 & Self::MANIFEST_RAW != 0
+  }
+
+  /// The same flags, but for either the public or the private store
+  pub fn with_private(self, private: bool) -> Self {
+    if private {
+      Self(self.0 | Self::PRIVATE_RAW)
+    } else {
+      Self(self.0 & !Self::PRIVATE_RAW)
+    }
+  }
 }
 
 impl BitOrAssign for Flags {
@@ -157,19 +330,101 @@ impl BitOrAssign for Flags {
 fn usage(program_name: &str, tag: Option<CmdTag>) {
   match tag {
     None => {
-      usageln!("{program_name} [{FULL_BUILD_FLAG}] [{PRIVATE_FLAG}] <command> [<args>]");
+      usageln!("{program_name} [{FULL_BUILD_FLAG}] [{PRIVATE_FLAG}] [{GZIP_FLAG}] [{QUIET_FLAG}] [{VERBOSE_FLAG}] [{DRY_RUN_FLAG}] [{MANIFEST_FLAG}] [{BRANCH_FLAG} <branch>] [{JOBS_FLAG} <jobs>] <command> [<args>]");
     }
     Some(CmdTag::RenderBatch) => {
-      usageln!("{program_name} [{FULL_BUILD_FLAG}] [{PRIVATE_FLAG}] {RENDER_BATCH_CMD}");
+      usageln!("{program_name} [{FULL_BUILD_FLAG}] [{PRIVATE_FLAG}] [{GZIP_FLAG}] [{QUIET_FLAG}] [{VERBOSE_FLAG}] [{DRY_RUN_FLAG}] [{MANIFEST_FLAG}] [{BRANCH_FLAG} <branch>] [{JOBS_FLAG} <jobs>] {RENDER_BATCH_CMD}");
     }
     Some(CmdTag::Render) => {
-      usageln!("{program_name} [{FULL_BUILD_FLAG}] [{PRIVATE_FLAG}] {RENDER_CMD} <repo-name>");
+      usageln!("{program_name} [{FULL_BUILD_FLAG}] [{PRIVATE_FLAG}] [{GZIP_FLAG}] [{QUIET_FLAG}] [{VERBOSE_FLAG}] [{DRY_RUN_FLAG}] [{MANIFEST_FLAG}] [{BRANCH_FLAG} <branch>] [{JOBS_FLAG} <jobs>] {RENDER_CMD} <repo-name>");
     }
     Some(CmdTag::Init) => {
-      usageln!("{program_name} [{PRIVATE_FLAG}] {INIT_CMD} <repo-name> <description>");
+      usageln!("{program_name} [{PRIVATE_FLAG}] [{QUIET_FLAG}] [{BARE_FLAG}] {INIT_CMD} <repo-name> <description>");
     }
     Some(CmdTag::Delete) => {
-      usageln!("{program_name} [{PRIVATE_FLAG}] {DELETE_CMD} <repo-name>");
+      usageln!("{program_name} [{PRIVATE_FLAG}] [{QUIET_FLAG}] [{PURGE_FLAG}] {DELETE_CMD} <repo-name>");
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(args: &[&str]) -> Result<Cmd, ()> {
+    let mut args = args.iter().map(|arg| arg.to_string());
+    Cmd::parse(&mut args, "yagit")
+  }
+
+  #[test]
+  fn render_batch_with_flags() {
+    let cmd = parse(&["--full-build", "--gzip", "render-batch"]).unwrap();
+
+    assert!(matches!(cmd.sub_cmd, SubCmd::RenderBatch));
+    assert!(cmd.flags.full_build());
+    assert!(cmd.flags.gzip());
+    assert!(!cmd.flags.private());
+    assert!(cmd.branch.is_none());
+    assert!(cmd.jobs.is_none());
+    assert!(!cmd.flags.manifest());
+
+    let cmd = parse(&["--manifest", "render-batch"]).unwrap();
+    assert!(cmd.flags.manifest());
+  }
+
+  #[test]
+  fn render_with_branch() {
+    let cmd = parse(&["--branch", "next", "render", "yagit"]).unwrap();
+
+    assert!(matches!(cmd.sub_cmd, SubCmd::Render { ref repo_name } if repo_name == "yagit"));
+    assert_eq!(cmd.branch.as_deref(), Some("next"));
+
+    let cmd = parse(&["--jobs", "4", "render", "yagit"]).unwrap();
+    assert_eq!(cmd.jobs, Some(4));
+  }
+
+  #[test]
+  fn init_and_delete() {
+    let cmd = parse(&["--private", "--bare", "init", "yagit", "A git site"]).unwrap();
+    assert!(matches!(
+      cmd.sub_cmd,
+      SubCmd::Init { ref repo_name, ref description }
+        if repo_name == "yagit" && description == "A git site"
+    ));
+    assert!(cmd.flags.private());
+    assert!(cmd.flags.bare());
+
+    let cmd = parse(&["--purge", "delete", "yagit"]).unwrap();
+    assert!(matches!(cmd.sub_cmd, SubCmd::Delete { ref repo_name } if repo_name == "yagit"));
+    assert!(cmd.flags.purge());
+  }
+
+  #[test]
+  fn invalid_command_lines() {
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["build"]).is_err());
+    assert!(parse(&["--fast", "render-batch"]).is_err());
+    assert!(parse(&["render"]).is_err());
+    assert!(parse(&["init", "yagit"]).is_err());
+    assert!(parse(&["--branch"]).is_err());
+    assert!(parse(&["--jobs", "0", "render-batch"]).is_err());
+    assert!(parse(&["--jobs", "many", "render-batch"]).is_err());
+    assert!(parse(&["--jobs", "2", "delete", "yagit"]).is_err());
+    assert!(parse(&["--bare", "render", "yagit"]).is_err());
+    assert!(parse(&["--purge", "render-batch"]).is_err());
+    assert!(parse(&["--manifest", "init", "yagit", "A git site"]).is_err());
+  }
+
+  #[test]
+  fn path_traversal_in_repo_names() {
+    assert!(parse(&["render", ".."]).is_err());
+    assert!(parse(&["render", "../../etc"]).is_err());
+    assert!(parse(&["render", "yagit/../.."]).is_err());
+    assert!(parse(&["render", ".hidden"]).is_err());
+    assert!(parse(&["init", "a/b", "A git site"]).is_err());
+    assert!(parse(&["delete", ""]).is_err());
+
+    assert!(parse(&["render", "yagit.rs"]).is_ok());
+    assert!(parse(&["render", "a..b"]).is_err());
+  }
+}
@@ -1,20 +1,272 @@
 //! Compile-time configuration keys
 
+use std::{env, path::{Path, PathBuf}};
+
 static_toml::static_toml! {
   static CONFIG = include_toml!("config.toml");
 }
 
 #[cfg(not(debug_assertions))]
-pub const OUTPUT_PATH: &str = CONFIG.output.path;
+const OUTPUT_PATH: &str = CONFIG.output.path;
 
 #[cfg(debug_assertions)]
-pub const OUTPUT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/site");
+const OUTPUT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/site");
 
 pub const TREE_SUBDIR:         &str = CONFIG.output.tree_subdir;
 pub const BLOB_SUBDIR:         &str = CONFIG.output.blob_subdir;
 pub const COMMIT_SUBDIR:       &str = CONFIG.output.commit_subdir;
 pub const PRIVATE_OUTPUT_ROOT: &str = CONFIG.output.private_output_root;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeUnits {
+  /// Powers of 1000: kB, MB, GB
+  Si,
+  /// Powers of 1024: KiB, MiB, GiB
+  Iec,
+}
+
+/// The URL `OUTPUT_PATH` is served at, used to construct absolute URLs
+pub const BASE_URL: Option<&str> = if CONFIG.site.base_url.is_empty() {
+  None
+} else {
+  Some(CONFIG.site.base_url)
+};
+
+/// The URLs of the assets linked from the header of every page
+pub const STYLESHEET: &str = CONFIG.site.stylesheet;
+pub const FAVICON:    &str = CONFIG.site.favicon;
+
+/// The language of the pages, set as the `lang` attribute of `<html>`
+pub const LANG: Option<&str> = if CONFIG.site.lang.is_empty() {
+  None
+} else {
+  Some(CONFIG.site.lang)
+};
+
+/// The direction of the text of the pages, set as the `dir` attribute of
+/// `<html>`
+pub const TEXT_DIR: Option<&str> = match CONFIG.site.dir.as_bytes() {
+  b""    => None,
+  b"ltr" => Some("ltr"),
+  b"rtl" => Some("rtl"),
+  _      => panic!("site.dir should be either \"ltr\", \"rtl\" or empty"),
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepoSort {
+  /// Newest projects first, by their first commit
+  Created,
+  /// Most recently active projects first, by their last commit
+  Updated,
+  /// Alphabetically, by name
+  Name,
+}
+
+pub const REPO_SORT: RepoSort = match CONFIG.site.repo_sort.as_bytes() {
+  b"created" => RepoSort::Created,
+  b"updated" => RepoSort::Updated,
+  b"name"    => RepoSort::Name,
+  _          => panic!("site.repo_sort should be either \"created\", \"updated\" or \"name\""),
+};
+
+pub const TEXT_LOC:   bool = CONFIG.output.text_loc;
+pub const ROBOTS_TXT: bool = CONFIG.output.robots_txt;
+
+pub const COLLAPSIBLE_DIFFS: bool = CONFIG.output.collapsible_diffs;
+
+/// `None` if blobs of any size should be displayed
+pub const MAX_BLOB_SIZE: Option<usize> = match CONFIG.output.max_blob_size {
+  0 => None,
+  n => Some(n as usize),
+};
+
+/// The permissions of output files, `None` if they should be left to the umask
+pub const FILE_MODE: Option<u32> = match CONFIG.output.file_mode {
+  0             => None,
+  n @ 1..=0o7777 => Some(n as u32),
+  _             => panic!("output.file_mode should be a valid file mode, such as 0o644"),
+};
+
+/// The permissions of output directories, `None` if they should be left to the
+/// umask
+pub const DIR_MODE: Option<u32> = match CONFIG.output.dir_mode {
+  0             => None,
+  n @ 1..=0o7777 => Some(n as u32),
+  _             => panic!("output.dir_mode should be a valid file mode, such as 0o755"),
+};
+
+pub const SIZE_UNITS: SizeUnits = match CONFIG.output.size_units.as_bytes() {
+  b"si"  => SizeUnits::Si,
+  b"iec" => SizeUnits::Iec,
+  _      => panic!("output.size_units should be either \"si\" or \"iec\""),
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DedupBlobs {
+  /// Write a copy of each raw blob for every path it's found at
+  Off,
+  /// Symlink the raw blobs to a single copy
+  Symlink,
+  /// Hardlink the raw blobs to a single copy
+  Hardlink,
+}
+
+pub const DEDUP_BLOBS: DedupBlobs = match CONFIG.output.dedup_blobs.as_bytes() {
+  b"off"      => DedupBlobs::Off,
+  b"symlink"  => DedupBlobs::Symlink,
+  b"hardlink" => DedupBlobs::Hardlink,
+  _           => panic!("output.dedup_blobs should be either \"off\", \"symlink\" or \"hardlink\""),
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TabMode {
+  /// Set the `tab-size` CSS property of code blocks
+  Css,
+  /// Replace tabs by spaces up to the next tab stop
+  Expand,
+}
+
+pub const TAB_WIDTH: usize = match CONFIG.output.tab_width {
+  n @ 1..=32 => n as usize,
+  _          => panic!("output.tab_width should be between 1 and 32"),
+};
+
+pub const TAB_MODE: TabMode = match CONFIG.output.tab_mode.as_bytes() {
+  b"css"    => TabMode::Css,
+  b"expand" => TabMode::Expand,
+  _         => panic!("output.tab_mode should be either \"css\" or \"expand\""),
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeZone {
+  /// The timezone of the host
+  Local,
+  Utc,
+  /// The timezone recorded in each commit
+  Commit,
+}
+
+pub const TIME_ZONE: TimeZone = match CONFIG.time.zone.as_bytes() {
+  b"local"  => TimeZone::Local,
+  b"utc"    => TimeZone::Utc,
+  b"commit" => TimeZone::Commit,
+  _         => panic!("time.zone should be either \"local\", \"utc\" or \"commit\""),
+};
+
+/// The names of dotfiles left out of the listings of tree pages
+pub const HIDDEN_DOTFILES: &[&str] = if CONFIG.tree.hide_dotfiles {
+  &CONFIG.tree.hidden_dotfiles
+} else {
+  &[]
+};
+
+/// The names of README files, in order of priority
+pub const README_NAMES: &[&str] = &CONFIG.readme.names;
+
+/// Whether math in markdown should be wrapped in MathJax delimiters
+pub const MATHJAX: bool = CONFIG.markdown.mathjax;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawHtml {
+  /// Leave raw HTML out of the output
+  Drop,
+  /// Display raw HTML as code
+  Escape,
+  /// Replace raw HTML by a placeholder comment
+  Comment,
+}
+
+/// What to do with the raw HTML in markdown documents, which is never emitted
+pub const RAW_HTML: RawHtml = match CONFIG.markdown.raw_html.as_bytes() {
+  b"drop"    => RawHtml::Drop,
+  b"escape"  => RawHtml::Escape,
+  b"comment" => RawHtml::Comment,
+  _          => panic!("markdown.raw_html should be either \"drop\", \"escape\" or \"comment\""),
+};
+
+/// The number of levels headings of READMEs are shifted down by, so that they
+/// fit below the `<h1>` of the page
+pub const README_HEADING_OFFSET: usize = match CONFIG.markdown.readme_heading_offset {
+  n @ 0..=5 => n as usize,
+  _         => panic!("markdown.readme_heading_offset should be between 0 and 5"),
+};
+
+/// Whether the bodies of commit messages should be rendered as markdown
+pub const COMMIT_MARKDOWN: bool = CONFIG.commit.markdown;
+
+/// Whether bare URLs and issue references in commit messages should be linked
+pub const AUTOLINK: bool = CONFIG.commit.autolink;
+
+/// The URL of issues, where `{name}` stands for the name of the repository and
+/// `{id}` for the number of the issue
+pub const ISSUE_URL: Option<&str> = if CONFIG.commit.issue_url.is_empty() {
+  None
+} else {
+  Some(CONFIG.commit.issue_url)
+};
+
+/// The minimum number of hex digits of abbreviated commit ids
+pub const SHORT_ID_LEN: usize = match CONFIG.commit.short_id_len {
+  n @ 4..=40 => n as usize,
+  _          => panic!("commit.short_id_len should be between 4 and 40"),
+};
+
+/// The number of most recent commits which get a page of their own, `None` if
+/// all of them should
+pub const MAX_COMMITS: Option<usize> = match CONFIG.log.max_commits {
+  0       => None,
+  n @ 1.. => Some(n as usize),
+  _       => panic!("log.max_commits should not be negative"),
+};
+
+/// The placeholder listed in the index for repositories without a
+/// description, `None` if nothing should be listed
+pub const NO_DESCRIPTION: Option<&str> = if CONFIG.index.no_description.is_empty() {
+  None
+} else {
+  Some(CONFIG.index.no_description)
+};
+
+pub const ARCHIVED_SECTION: bool = CONFIG.index.archived_section;
+
+/// The number of most recent commits listed in the site-wide Atom feed, `None`
+/// if no feed should be written
+pub const FEED_MAX_ENTRIES: Option<usize> = match CONFIG.feed.max_entries {
+  0       => None,
+  n @ 1.. => Some(n as usize),
+  _       => panic!("feed.max_entries should not be negative"),
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OlderCommits {
+  /// List the commits without a page in the log, without linking to them
+  List,
+  /// Leave the commits without a page out of the log
+  Omit,
+}
+
+pub const OLDER_COMMITS: OlderCommits = match CONFIG.log.older_commits.as_bytes() {
+  b"list" => OlderCommits::List,
+  b"omit" => OlderCommits::Omit,
+  _       => panic!("log.older_commits should be either \"list\" or \"omit\""),
+};
+
+pub const DIFF_CONTEXT_LINES:   u32 = CONFIG.diff.context_lines as u32;
+pub const DIFF_INTERHUNK_LINES: u32 = CONFIG.diff.interhunk_lines as u32;
+
+pub const DIFF_RENAME_THRESHOLD: u16 = match CONFIG.diff.rename_threshold {
+  n @ 0..=100 => n as u16,
+  _           => panic!("diff.rename_threshold should be between 0 and 100"),
+};
+pub const DIFF_COPY_THRESHOLD: u16 = match CONFIG.diff.copy_threshold {
+  n @ 0..=100 => n as u16,
+  _           => panic!("diff.copy_threshold should be between 0 and 100"),
+};
+
+pub const DATE_TIME_FMT: &str = CONFIG.time.datetime_fmt;
+pub const DATE_FMT:      &str = CONFIG.time.date_fmt;
+pub const FULL_DATE_FMT: &str = CONFIG.time.full_date_fmt;
+
 #[cfg(not(debug_assertions))]
 pub const GROUP_ID: u32  = CONFIG.output.group_id as u32;
 
@@ -22,12 +274,146 @@ pub const GROUP_ID: u32  = CONFIG.output.group_id as u32;
 pub const GIT_USER: &str = CONFIG.git.user;
 pub const OWNER:    &str = CONFIG.git.store_owner;
 
+/// The URL repositories can be cloned from, where `{name}` stands for the
+/// name of the repository
+pub const CLONE_URL: &str = CONFIG.git.clone_url;
+
 #[cfg(debug_assertions)]
-pub const STORE_PATH:         &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/public");
+const STORE_PATH:         &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/public");
 #[cfg(debug_assertions)]
-pub const PRIVATE_STORE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/private");
+const PRIVATE_STORE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test/private");
 
 #[cfg(not(debug_assertions))]
-pub const STORE_PATH:         &str = CONFIG.git.store_path;
+const STORE_PATH:         &str = CONFIG.git.store_path;
 #[cfg(not(debug_assertions))]
-pub const PRIVATE_STORE_PATH: &str = CONFIG.git.private_store_path;
+const PRIVATE_STORE_PATH: &str = CONFIG.git.private_store_path;
+
+// ============================================================================
+/// The locations of the repository stores and of the output
+#[derive(Clone, Debug)]
+pub struct Paths {
+  /// The directory the pages are rendered to
+  pub output:        PathBuf,
+  /// The directory public repositories are stored in
+  pub store:         PathBuf,
+  /// The directory private repositories are stored in
+  pub private_store: PathBuf,
+}
+
+impl Paths {
+  /// Reads the paths in the configuration, expanding the environment
+  /// variables in them, such as `$HOME` or `${XDG_DATA_HOME}`
+  pub fn from_config() -> Result<Self, ()> {
+    Ok(Self {
+      output:        expand_env("output.path", OUTPUT_PATH)?.into(),
+      store:         expand_env("git.store_path", STORE_PATH)?.into(),
+      private_store: expand_env("git.private_store_path", PRIVATE_STORE_PATH)?.into(),
+    })
+  }
+
+  /// The store of either the public or the private repositories
+  pub fn repos_dir(&self, private: bool) -> &Path {
+    if private { &self.private_store } else { &self.store }
+  }
+
+  /// The directory the pages of either the public or the private
+  /// repositories are rendered to
+  pub fn output_dir(&self, private: bool) -> PathBuf {
+    if private {
+      self.output.join(PRIVATE_OUTPUT_ROOT)
+    } else {
+      self.output.clone()
+    }
+  }
+}
+
+/// Substitutes the environment variables in `path`, written as `$NAME` or
+/// `${NAME}`
+///
+/// `$$` stands for a literal `$`. Fails if any of the variables is unset.
+fn expand_env(key: &str, path: &str) -> Result<String, ()> {
+  let mut result = String::with_capacity(path.len());
+  let mut rest = path;
+
+  while let Some(i) = rest.find('$') {
+    result.push_str(&rest[..i]);
+    rest = &rest[i+1..];
+
+    let (var, tail) = if let Some(braced) = rest.strip_prefix('{') {
+      let Some(end) = braced.find('}') else {
+        errorln!("Unterminated ${{ in {key} ({path:?})");
+        return Err(());
+      };
+      (&braced[..end], &braced[end+1..])
+    } else if let Some(tail) = rest.strip_prefix('$') {
+      result.push('$');
+      rest = tail;
+      continue;
+    } else {
+      let end = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+      (&rest[..end], &rest[end..])
+    };
+
+    if var.is_empty() {
+      errorln!("Missing environment variable name after $ in {key} ({path:?})");
+      return Err(());
+    }
+
+    match env::var(var) {
+      Ok(value) => result.push_str(&value),
+      Err(_)    => {
+        errorln!("{key} ({path:?}) refers to the environment variable {var:?}, which is not set");
+        return Err(());
+      }
+    }
+
+    rest = tail;
+  }
+  result.push_str(rest);
+
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn expands_variables() {
+    env::set_var("YAGIT_TEST_EXPAND", "/srv/git");
+
+    assert_eq!(expand_env("key", "$YAGIT_TEST_EXPAND/public"),
+               Ok("/srv/git/public".to_string()));
+    assert_eq!(expand_env("key", "${YAGIT_TEST_EXPAND}public"),
+               Ok("/srv/gitpublic".to_string()));
+    assert_eq!(expand_env("key", "/no/variables"),
+               Ok("/no/variables".to_string()));
+  }
+
+  #[test]
+  fn escaped_dollar_signs() {
+    assert_eq!(expand_env("key", "/srv/$$git"), Ok("/srv/$git".to_string()));
+    assert_eq!(expand_env("key", "$$$$"), Ok("$$".to_string()));
+    // $$ isn't the start of a variable name
+    assert_eq!(expand_env("key", "$$YAGIT_TEST_UNSET"),
+               Ok("$YAGIT_TEST_UNSET".to_string()));
+  }
+
+  #[test]
+  fn unset_variables() {
+    env::remove_var("YAGIT_TEST_UNSET");
+
+    assert_eq!(expand_env("key", "$YAGIT_TEST_UNSET/public"), Err(()));
+    assert_eq!(expand_env("key", "${YAGIT_TEST_UNSET}"), Err(()));
+  }
+
+  #[test]
+  fn malformed_variables() {
+    assert_eq!(expand_env("key", "${YAGIT_TEST_EXPAND"), Err(()));
+    assert_eq!(expand_env("key", "/srv/${"), Err(()));
+    assert_eq!(expand_env("key", "/srv/$"), Err(()));
+    assert_eq!(expand_env("key", "${}"), Err(()));
+  }
+}
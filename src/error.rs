@@ -0,0 +1,81 @@
+//! I/O errors which remember the file they happened at
+//!
+//! Errors are logged as soon as they are encountered, but they are also
+//! propagated up to `main`, which reports the repository which failed to
+//! render. Wrapping them in a [`PathError`] keeps the file and the operation
+//! which failed in that report, which is all we get when running from a hook.
+
+use std::{
+  error::Error,
+  fmt::{self, Display},
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+/// An I/O error, along with the operation which failed and its file
+#[derive(Debug)]
+pub struct PathError {
+  /// What we were doing, e.g. "create" or "remove"
+  op:     &'static str,
+  path:   PathBuf,
+  source: io::Error,
+}
+
+impl Display for PathError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "failed to {op} {path:?}: {source}",
+              op = self.op, path = self.path, source = self.source)
+  }
+}
+
+impl Error for PathError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    Some(&self.source)
+  }
+}
+
+/// Wraps `e` in a [`PathError`], keeping its kind
+pub fn with_path<P: AsRef<Path>>(e: io::Error, op: &'static str, path: P) -> io::Error {
+  let path = path.as_ref().to_path_buf();
+  io::Error::new(e.kind(), PathError { op, path, source: e })
+}
+
+/// A writer whose errors are reported along with the path of the file being
+/// written
+pub struct PathWriter<W> {
+  inner: W,
+  path:  PathBuf,
+}
+
+impl<W: Write> PathWriter<W> {
+  pub fn new<P: AsRef<Path>>(inner: W, path: P) -> Self {
+    Self { inner, path: path.as_ref().to_path_buf() }
+  }
+}
+
+impl<W: Write> Write for PathWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.inner.write(buf).map_err(|e| with_path(e, "write", &self.path))
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush().map_err(|e| with_path(e, "write", &self.path))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn errors_mention_their_path() {
+    let e = io::Error::from(io::ErrorKind::PermissionDenied);
+    let e = with_path(e, "create", "/var/www/git/yagit/tree/index.html");
+
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(
+      e.to_string(),
+      "failed to create \"/var/www/git/yagit/tree/index.html\": permission denied",
+    );
+  }
+}
@@ -1,6 +1,6 @@
-//! HTML Escaping
+//! HTML, URL and JSON Escaping
 //!
-//! Stolen from pulldown-cmark-escape
+//! HTML escaping stolen from pulldown-cmark-escape
 //! <https://github.com/pulldown-cmark/pulldown-cmark/>
 
 use std::fmt::{self, Display};
@@ -32,7 +32,16 @@ impl Display for Escaped<'_> {
     }
   }
 
-  #[cfg(not(target_arch = "x86_64"))]
+  #[cfg(target_arch = "aarch64")]
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if std::arch::is_aarch64_feature_detected!("neon") {
+      simd::fmt_escaped_html(self.0, f)
+    } else {
+      fmt_escaped_html_scalar(self.0, f)
+    }
+  }
+
+  #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     fmt_escaped_html_scalar(self.0, f)
   }
@@ -68,12 +77,131 @@ fn fmt_escaped_html_scalar(
   f.write_str(&s[mark..])
 }
 
+/// A wrapper for strings escaped for use inside of JSON string literals
+///
+/// Does *not* include the surrounding quotes.
+pub struct JsonEscaped<'a>(pub &'a str);
+
+impl Display for JsonEscaped<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let s = self.0;
+    let mut mark = 0;
+
+    for (i, c) in s.bytes().enumerate() {
+      let escape_seq = match c {
+        b'"'        => Some("\\\""),
+        b'\\'       => Some("\\\\"),
+        b'\n'       => Some("\\n"),
+        b'\r'       => Some("\\r"),
+        b'\t'       => Some("\\t"),
+        0x00..=0x1f => None, // other control characters
+        b'<'        => None, // so that JSON can be embedded in <script>
+        _           => continue,
+      };
+
+      f.write_str(&s[mark..i])?;
+      match escape_seq {
+        Some(escape_seq) => f.write_str(escape_seq)?,
+        None             => write!(f, "\\u{c:04x}")?,
+      }
+      mark = i + 1; // all escaped characters are ASCII
+    }
+
+    f.write_str(&s[mark..])
+  }
+}
+
+/// A wrapper for URLs escaped for use inside of HTML attributes, such as `href`
+/// and `src`
+///
+/// Characters which are invalid in URLs (e.g. spaces, control characters and
+/// non-ASCII characters) are percent-encoded. Characters which are valid in
+/// URLs, including `%` and reserved characters such as `/`, `?` and `#`, are
+/// left as is, so that already-encoded URLs are kept intact.
+pub struct EscapedUrl<'a>(pub &'a str);
+
+impl Display for EscapedUrl<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt_escaped_url(self.0, f, |_| false)
+  }
+}
+
+/// A wrapper for file paths escaped for use in the path of URLs inside of HTML
+/// attributes
+///
+/// This is the same as [`EscapedUrl`], except that `#`, `?`, `%` and `+` are
+/// also percent-encoded, since they are valid in file names but have a special
+/// meaning in URLs. `/` is left as is, as the separator of path segments.
+///
+/// `&` and `'` are percent-encoded instead of escaped as HTML, so that the
+/// result is a valid URL by itself.
+pub struct EscapedPath<'a>(pub &'a str);
+
+impl Display for EscapedPath<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt_escaped_url(self.0, f, |c| matches!(c, '#' | '?' | '%' | '+' | '&' | '\''))
+  }
+}
+
+/// Percent-encodes the characters which are invalid in URLs, as well as the
+/// ones for which `reserved` returns `true`
+fn fmt_escaped_url(
+  s: &str,
+  f: &mut fmt::Formatter<'_>,
+  reserved: fn(char) -> bool,
+) -> fmt::Result {
+  let mut mark = 0;
+
+  for (i, c) in s.char_indices() {
+    let percent_encode = !c.is_ascii() || reserved(c) || matches!(
+      c,
+      '\0'..=' ' | '\x7f' |
+      '"' | '<' | '>' | '\\' | '^' | '`' | '{' | '|' | '}'
+    );
+
+    if percent_encode {
+      f.write_str(&s[mark..i])?;
+      for b in c.encode_utf8(&mut [0; 4]).bytes() {
+        write!(f, "%{b:02X}")?;
+      }
+    } else if let Some(escape_seq) = ESCAPE_TABLE[c as usize] {
+      // '&' and '\'' are valid in URLs, but not in HTML attributes
+      f.write_str(&s[mark..i])?;
+      f.write_str(escape_seq)?;
+    } else {
+      continue;
+    }
+    mark = i + c.len_utf8();
+  }
+
+  f.write_str(&s[mark..])
+}
+
 // stolen from pulldown-cmark-escape
-#[cfg(target_arch = "x86_64")]
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
 mod simd {
-  use std::{arch::x86_64::*, mem, fmt};
+  use std::{mem, fmt};
+  #[cfg(target_arch = "x86_64")]
+  use std::arch::x86_64::*;
+  #[cfg(target_arch = "aarch64")]
+  use std::arch::aarch64::*;
 
-  const VECTOR_SIZE: usize = mem::size_of::<__m128i>();
+  #[cfg(target_arch = "x86_64")]
+  pub const VECTOR_SIZE: usize = mem::size_of::<__m128i>();
+  #[cfg(target_arch = "aarch64")]
+  pub const VECTOR_SIZE: usize = mem::size_of::<uint8x16_t>();
+
+  const LOOKUP_TABLE: [u8; VECTOR_SIZE] = create_lookup();
+  const fn create_lookup() -> [u8; VECTOR_SIZE] {
+    let mut table = [0; VECTOR_SIZE];
+    table[(b'<'  & 0x0f) as usize] = b'<';
+    table[(b'>'  & 0x0f) as usize] = b'>';
+    table[(b'&'  & 0x0f) as usize] = b'&';
+    table[(b'"'  & 0x0f) as usize] = b'"';
+    table[(b'\'' & 0x0f) as usize] = b'\'';
+    table[0]                       = 0b01111111;
+    table
+  }
 
   #[inline]
   pub fn fmt_escaped_html(s: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -146,6 +274,7 @@ mod simd {
   }
 
 
+  #[cfg(target_arch = "x86_64")]
   #[inline]
   #[target_feature(enable = "ssse3")]
   /// Computes a byte mask at given offset in the byte buffer. Its first 16
@@ -157,18 +286,6 @@ mod simd {
   unsafe fn compute_mask(bytes: &[u8], offset: usize) -> i32 {
     debug_assert!(bytes.len() >= offset + VECTOR_SIZE);
 
-    const LOOKUP_TABLE: [u8; VECTOR_SIZE] = create_lookup();
-    const fn create_lookup() -> [u8; VECTOR_SIZE] {
-      let mut table = [0; VECTOR_SIZE];
-      table[(b'<'  & 0x0f) as usize] = b'<';
-      table[(b'>'  & 0x0f) as usize] = b'>';
-      table[(b'&'  & 0x0f) as usize] = b'&';
-      table[(b'"'  & 0x0f) as usize] = b'"';
-      table[(b'\'' & 0x0f) as usize] = b'\'';
-      table[0]                       = 0b01111111;
-      table
-    }
-
     let lookup_table = _mm_loadu_si128(
       LOOKUP_TABLE.as_ptr() as *const __m128i
     );
@@ -195,4 +312,229 @@ mod simd {
     // special character and a 0 is a non-HTML byte
     _mm_movemask_epi8(matches)
   }
+
+  #[cfg(target_arch = "aarch64")]
+  #[inline]
+  #[target_feature(enable = "neon")]
+  /// Computes a byte mask at given offset in the byte buffer. Its first 16
+  /// (least significant) bits correspond to whether there is an HTML special
+  /// byte at the first VECTOR_SIZE bytes `bytes[offset..]`.
+  ///
+  /// It is only safe to call this function when `bytes.len() >= offset +
+  /// VECTOR_SIZE`.
+  unsafe fn compute_mask(bytes: &[u8], offset: usize) -> i32 {
+    debug_assert!(bytes.len() >= offset + VECTOR_SIZE);
+
+    // the weight of each lane in the final bitmask
+    const BIT_TABLE: [u8; VECTOR_SIZE] = [
+      1, 2, 4, 8, 16, 32, 64, 128,
+      1, 2, 4, 8, 16, 32, 64, 128,
+    ];
+
+    let lookup_table = vld1q_u8(LOOKUP_TABLE.as_ptr());
+    let vector = vld1q_u8(bytes.as_ptr().add(offset));
+
+    // mask the vector using the lookup table, as in the x86 implementation:
+    // unlike PSHUFB, TBL maps out-of-range indices to 0 instead of only
+    // looking at their lower nibbles, so we have to extract the nibbles
+    // ourselves
+    let nibbles = vandq_u8(vector, vdupq_n_u8(0x0f));
+    let masked = vqtbl1q_u8(lookup_table, nibbles);
+
+    // compare the original vector to the masked one: matching bytes are set
+    // to 0xff and all other bytes are set to 0
+    let matches = vceqq_u8(masked, vector);
+
+    // NEON has no equivalent of PMOVMSKB, so we select a different bit for
+    // each byte in the lower and upper halves of the vector and add them up
+    let bits = vandq_u8(matches, vld1q_u8(BIT_TABLE.as_ptr()));
+    let lo = vaddv_u8(vget_low_u8(bits)) as i32;
+    let hi = vaddv_u8(vget_high_u8(bits)) as i32;
+
+    lo | (hi << 8)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct Scalar<'a>(&'a str);
+
+  impl Display for Scalar<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      fmt_escaped_html_scalar(self.0, f)
+    }
+  }
+
+  #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+  struct Simd<'a>(&'a str);
+
+  #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+  impl Display for Simd<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      simd::fmt_escaped_html(self.0, f)
+    }
+  }
+
+  #[cfg(target_arch = "x86_64")]
+  fn simd_supported() -> bool {
+    is_x86_feature_detected!("ssse3")
+  }
+
+  #[cfg(target_arch = "aarch64")]
+  fn simd_supported() -> bool {
+    std::arch::is_aarch64_feature_detected!("neon")
+  }
+
+  /// A xorshift PRNG, so that failures are reproducible
+  struct Rng(u64);
+
+  impl Rng {
+    fn next(&mut self) -> u64 {
+      self.0 ^= self.0 << 13;
+      self.0 ^= self.0 >> 7;
+      self.0 ^= self.0 << 17;
+      self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+      (self.next() % n as u64) as usize
+    }
+  }
+
+  #[test]
+  fn scalar_escapes_special_characters() {
+    assert_eq!(
+      Scalar("<a href=\"x\">'&'</a>").to_string(),
+      "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;",
+    );
+  }
+
+  #[test]
+  fn paths_encode_reserved_characters() {
+    assert_eq!(EscapedPath("C#/main.cs").to_string(), "C%23/main.cs");
+    assert_eq!(EscapedPath("why?.txt").to_string(), "why%3F.txt");
+    assert_eq!(EscapedPath("100%done.txt").to_string(), "100%25done.txt");
+    assert_eq!(EscapedPath("my file.md").to_string(), "my%20file.md");
+    assert_eq!(EscapedPath("c++/a+b.h").to_string(), "c%2B%2B/a%2Bb.h");
+    assert_eq!(EscapedPath("café & co").to_string(), "caf%C3%A9%20%26%20co");
+  }
+
+  #[test]
+  fn urls_keep_reserved_characters() {
+    assert_eq!(
+      EscapedUrl("https://example.com/a b?q=1&r=%20#top").to_string(),
+      "https://example.com/a%20b?q=1&amp;r=%20#top",
+    );
+  }
+
+  #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+  fn assert_simd_matches_scalar(s: &str) {
+    assert_eq!(Simd(s).to_string(), Scalar(s).to_string(), "input: {s:?}");
+  }
+
+  /// Lengths around multiples of `VECTOR_SIZE`, where the final iteration of
+  /// the SIMD loop overlaps with the previous one by different amounts
+  #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+  fn boundary_lengths() -> impl Iterator<Item = usize> {
+    (0..=4).flat_map(|n| {
+      let len = n * simd::VECTOR_SIZE;
+      len.saturating_sub(2)..=len + 2
+    })
+  }
+
+  #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+  #[test]
+  fn simd_matches_scalar() {
+    const CHARS: &[char] = &[
+      'a', 'Z', '0', ' ', '\n', '\0', '<', '>', '&', '"', '\'', '\u{7f}',
+      'é', 'ÿ', '€', '𝄞',
+    ];
+
+    if !simd_supported() {
+      return;
+    }
+
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+    for len in 0..4 * simd::VECTOR_SIZE {
+      for _ in 0..64 {
+        let s: String = (0..len).map(|_| CHARS[rng.below(CHARS.len())]).collect();
+        assert_simd_matches_scalar(&s);
+      }
+    }
+  }
+
+  #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+  #[test]
+  fn simd_matches_scalar_on_random_bytes() {
+    if !simd_supported() {
+      return;
+    }
+
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    for _ in 0..2048 {
+      let len = rng.below(8 * simd::VECTOR_SIZE);
+      let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+      assert_simd_matches_scalar(&String::from_utf8_lossy(&bytes));
+    }
+  }
+
+  #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+  #[test]
+  fn simd_matches_scalar_on_special_characters() {
+    const SPECIAL: &[u8] = b"<>&\"'";
+
+    if !simd_supported() {
+      return;
+    }
+
+    let mut rng = Rng(0xd1b54a32d192ed03);
+    for len in boundary_lengths() {
+      // strings made only of special characters
+      for _ in 0..64 {
+        let s: String = (0..len)
+          .map(|_| SPECIAL[rng.below(SPECIAL.len())] as char)
+          .collect();
+        assert_simd_matches_scalar(&s);
+      }
+
+      // a single special character at every position
+      for i in 0..len {
+        for &c in SPECIAL {
+          let mut s = vec![b'a'; len];
+          s[i] = c;
+          assert_simd_matches_scalar(std::str::from_utf8(&s).unwrap());
+        }
+      }
+
+      // bytes which share their lower nibble with special characters
+      for i in 0..len {
+        for c in [b'\x0c', b'\x1e', b'\x16', b'\x12', b'\x17', b'\x00', b'l', b'n'] {
+          let mut s = vec![b'<'; len];
+          s[i] = c;
+          assert_simd_matches_scalar(std::str::from_utf8(&s).unwrap());
+        }
+      }
+    }
+  }
+
+  #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+  #[test]
+  fn simd_matches_scalar_on_multibyte_characters() {
+    if !simd_supported() {
+      return;
+    }
+
+    // multi-byte characters straddling the vector boundaries, whose
+    // continuation bytes have the high bit set
+    for len in boundary_lengths() {
+      for i in 0..len {
+        let mut s: String = "&".repeat(i);
+        s.push('𝄞');
+        s.push_str(&"<".repeat(len - i));
+        assert_simd_matches_scalar(&s);
+      }
+    }
+  }
 }
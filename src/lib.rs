@@ -0,0 +1,4809 @@
+//! Rendering of static pages for git repositories
+//!
+//! The `yagit` binary is a thin command-line wrapper around this library: a
+//! batch is rendered by collecting the repositories in a store with
+//! [`RepoInfo::index`], rendering each of them with a [`RepoRenderer`] and
+//! finally rendering the index of projects with [`render_index`].
+//!
+//! The locations of the stores and of the output are passed explicitly as
+//! [`Paths`], while other options are read from the `config.toml` the crate
+//! was compiled with.
+//!
+//! Errors are logged as soon as they are encountered, so most functions simply
+//! return `Err(())` on failure.
+#![allow(clippy::result_unit_err)]
+
+use std::{
+  io::{self, BufRead, Read, Write},
+  fs::{self, File},
+  path::{Path, PathBuf},
+  fmt::{self, Display},
+  collections::{HashMap, HashSet},
+  time::Instant,
+  os::unix::{self, fs::{PermissionsExt, MetadataExt}},
+  cell::RefCell,
+  rc::Rc,
+  borrow::Cow,
+  sync::{Mutex, MutexGuard, atomic::{AtomicBool, Ordering}},
+  thread,
+  cmp,
+};
+use flate2::{write::GzEncoder, Compression};
+use git2::{
+  Repository,
+  Tree,
+  Commit,
+  ObjectType,
+  Patch,
+  Delta,
+  DiffDelta,
+  DiffLine,
+  DiffLineType,
+  DiffOptions,
+  DiffFindOptions,
+  Time,
+  Oid,
+  Odb,
+  BranchType,
+  Reference,
+  TreeWalkMode,
+  TreeWalkResult,
+};
+
+use time::{DateTime, Date, FullDate, RelativeTime, Rfc3339};
+use command::Flags;
+use config::{
+  PRIVATE_OUTPUT_ROOT,
+  TREE_SUBDIR,
+  BLOB_SUBDIR,
+  COMMIT_SUBDIR,
+  SizeUnits,
+  TabMode,
+  OlderCommits,
+  DedupBlobs,
+  RepoSort,
+};
+use escape::{Escaped, EscapedUrl, EscapedPath, JsonEscaped};
+use signature::Signer;
+use repo_config::RepoConfig;
+use state::{State, STATE_FILE};
+use sha256::Sha256;
+use error::{with_path, PathWriter};
+use attr::Attributes;
+use config::Paths;
+use log::Stats;
+
+#[macro_use]
+pub mod log;
+
+mod escape;
+mod markdown;
+pub mod time;
+pub mod command;
+pub mod config;
+mod signature;
+mod trailers;
+mod repo_config;
+mod state;
+mod sha256;
+mod error;
+mod attr;
+
+/// The directory the deduplicated raw blobs are written to, relative to the
+/// output directory of the repository, if `output.dedup_blobs` is set
+const BLOBS_DIR: &str = ".blobs";
+
+/// The directory the branches other than the main one are rendered to,
+/// relative to the output directory of the repository
+const BRANCHES_DIR: &str = "branches";
+
+/// Documents at the root of the repository which are rendered to their own
+/// pages, listed in the order they appear in the navigation bar
+const SPECIAL_DOCS: &[SpecialDoc] = &[
+  // TODO: [feature]: parse the license from content?
+  SpecialDoc {
+    name: "LICENSE", page: "license.html", label: "license",
+    format: ReadmeFormat::Txt,
+  },
+  SpecialDoc {
+    name: "CONTRIBUTING.md", page: "contributing.html", label: "contributing",
+    format: ReadmeFormat::Md,
+  },
+  SpecialDoc {
+    name: "CONTRIBUTING", page: "contributing.html", label: "contributing",
+    format: ReadmeFormat::Txt,
+  },
+  SpecialDoc {
+    name: "CHANGELOG.md", page: "changelog.html", label: "changelog",
+    format: ReadmeFormat::Md,
+  },
+  SpecialDoc {
+    name: "CHANGELOG", page: "changelog.html", label: "changelog",
+    format: ReadmeFormat::Txt,
+  },
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PageTitle<'a> {
+  Index,
+  Summary { repo_name: &'a str },
+  Log { repo_name: &'a str },
+  TreeEntry { repo_name: &'a str, path: &'a Path, },
+  Commit { repo_name: &'a str, summary: &'a str },
+  Doc { repo_name: &'a str, label: &'a str },
+  Search,
+  NotFound,
+}
+
+impl Display for PageTitle<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match *self {
+      PageTitle::Index => write!(f, "personal projects"),
+      PageTitle::Summary { repo_name } => write!(f, "{}", Escaped(repo_name)),
+      PageTitle::TreeEntry { repo_name, path } => {
+        write!(f, "/{path} at {repo}",
+                  repo = Escaped(repo_name),
+                  path = Escaped(&path.to_string_lossy()))
+      }
+      PageTitle::Log { repo_name } => write!(f, "{} log", Escaped(repo_name)),
+      PageTitle::Commit { repo_name, summary } => {
+        write!(f, "{repo}: {summary}",
+                  repo = Escaped(repo_name),
+                  summary = Escaped(summary.trim()))
+      }
+      PageTitle::Doc { repo_name, label } => {
+        write!(f, "{repo} {label}", repo = Escaped(repo_name))
+      }
+      PageTitle::Search => write!(f, "search"),
+      PageTitle::NotFound => write!(f, "page not found"),
+    }
+  }
+}
+
+/// A repository in one of the stores, along with the metadata listed in the
+/// index of projects
+pub struct RepoInfo {
+  pub name:        String,
+  pub owner:       String,
+  pub description: Option<String>,
+  pub clone_url:   String,
+
+  pub repo:         Repository,
+  pub last_commit:  Time,
+  pub first_commit: u32,
+
+  /// Whether the pages of the repository are rendered under the private
+  /// output root: either because it's in the private store, or because it
+  /// was marked as private
+  pub private: bool,
+  /// Whether the repository was marked as archived, either with a
+  /// `.git/archived` file or with the `yagit.archived` option
+  pub archived: bool,
+  /// The category the repository is listed under in the index, read from a
+  /// `.git/category` file or from the `yagit.category` option
+  pub category: Option<String>,
+}
+
+impl RepoInfo {
+  /// Opens the repository at `path`, where `private` is whether it's in the
+  /// private store
+  pub fn open<S>(path: PathBuf, name: S, private: bool) -> Result<Self, ()>
+  where
+    S: AsRef<str>,
+  {
+    Self::open_with_dates(path, name, private, None)
+  }
+
+  /// Opens a repository, reading the dates of its first and last commits from
+  /// its `info.json` in the output directory of `paths` if one is given: which
+  /// is much cheaper than walking its history
+  fn open_with_dates<S>(
+    path: PathBuf,
+    name: S,
+    private: bool,
+    rendered: Option<&Paths>,
+  ) -> Result<Self, ()>
+  where
+    S: AsRef<str>,
+  {
+    let repo = match Repository::open(&path) {
+      Ok(repo) => repo,
+      Err(_)   => {
+        errorln!("Could not open repository in {path:?}");
+        return Err(());
+      }
+    };
+
+    // freshly initialized repositories are common: there's nothing to render
+    // for them, but that's no reason to fail
+    if repo.is_empty().unwrap_or(false) {
+      warnln!("Repository {name:?} has no commits yet",
+              name = name.as_ref());
+      return Err(());
+    }
+
+    let private = private || is_marked_private(&repo);
+    let archived = is_marked_archived(&repo);
+
+    let category = fs::read_to_string(repo.path().join("category"))
+      .ok()
+      .or_else(|| git_config_str(&repo, &["yagit.category"]))
+      .map(|category| category.trim().to_string())
+      .filter(|category| !category.is_empty());
+
+    let rendered_dates = rendered.and_then(|paths| {
+      let mut info_path = paths.output_dir(private);
+      info_path.push(name.as_ref());
+      info_path.push("info.json");
+      read_rendered_dates(&info_path)
+    });
+    let (first_commit, last_commit) = if let Some(dates) = rendered_dates {
+      dates
+    } else {
+      let mut revwalk = repo.revwalk().unwrap();
+      if revwalk.push_head().is_err() {
+        errorln!("Couldn't retrieve repository HEAD in {name:?}. Did you push to \"master\" instead of \"main\"?",
+                 name = name.as_ref());
+        return Err(());
+      }
+
+      revwalk.flatten().fold(
+        (u32::MAX, Time::new(i64::MIN, 0)),
+        |(min, max), commit_id| {
+          let commit = repo.find_commit(commit_id).unwrap();
+          let commit_time = commit.author().when();
+
+          (
+            cmp::min(min, commit_time.seconds() as u32),
+            cmp::max_by(
+              max,
+              commit_time,
+              |t1, t2| t1.seconds().cmp(&t2.seconds()),
+            ),
+          )
+        }
+      )
+    };
+
+    // fall back to the git configuration for repositories which weren't
+    // initialized by yagit
+    let owner = {
+      let mut owner_path = path.clone();
+      if !repo.is_bare() { owner_path.push(".git"); }
+      owner_path.push("owner");
+
+      let mut owner = String::with_capacity(32);
+      let read = File::open(owner_path)
+        .map(|mut f| f.read_to_string(&mut owner));
+
+      match read {
+        Ok(Ok(_))  => owner,
+        Ok(Err(e)) => {
+          errorln!("Could not read the owner of {path:?}: {e}");
+          return Err(());
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+          git_config_str(&repo, &["yagit.owner", "gitweb.owner"])
+            .unwrap_or_else(|| String::from(config::OWNER))
+        }
+        Err(e) => {
+          errorln!("Could not read the owner of {path:?}: {e}");
+          return Err(());
+        }
+      }
+    };
+
+    let description = {
+      let mut dsc_path = path.clone();
+      if !repo.is_bare() { dsc_path.push(".git"); }
+      dsc_path.push("description");
+      let mut dsc = String::with_capacity(512);
+
+      let read = File::open(dsc_path)
+        .map(|mut f| f.read_to_string(&mut dsc));
+
+      match read {
+        // the placeholder description file created by `git init`
+        Ok(Ok(_)) if dsc.starts_with("Unnamed repository;") => {
+          git_config_str(&repo, &["yagit.description", "gitweb.description"])
+        }
+        Ok(Ok(_))  => Some(dsc),
+        Ok(Err(e)) => {
+          warnln!("Could not read the description of {path:?}: {e}");
+          None
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+          git_config_str(&repo, &["yagit.description", "gitweb.description"])
+        }
+        Err(e) => {
+          warnln!("Could not read the description of {path:?}: {e}");
+          None
+        }
+      }
+    };
+
+    let repo_config = RepoConfig::read(&repo, name.as_ref());
+    let description = repo_config.description.or(description);
+    let clone_url = repo_config
+      .clone_url
+      .unwrap_or_else(|| config::CLONE_URL.replace("{name}", name.as_ref()));
+
+    Ok(Self {
+      name: String::from(name.as_ref()),
+      owner,
+      description,
+      clone_url,
+      repo,
+      first_commit,
+      last_commit,
+      private,
+      archived,
+      category,
+    })
+  }
+
+  /// Returns an index of the repositories in `paths.store` or
+  /// `paths.private_store`, in the order set by `site.repo_sort`.
+  ///
+  /// Repositories in `paths.store` may be marked as private, in which case
+  /// they are rendered under the private output root all the same.
+  pub fn index(paths: &Paths, private: bool) -> Result<Vec<Self>, ()> {
+    Self::index_with_dates(paths, private, false)
+  }
+
+  /// Returns an (ordered) index of the repositories listed in the index of
+  /// projects of either the public or the private output root
+  ///
+  /// Unlike [`RepoInfo::index`], this includes the repositories in
+  /// `paths.store` which were marked as private in the private index (and
+  /// leaves them out of the public one). The dates of the first and last
+  /// commits of the repositories which were already rendered are read from
+  /// their `info.json` instead of walking their history: which is enough to
+  /// render the index of projects, but the dates of repositories which were
+  /// pushed to since they were last rendered may be outdated.
+  pub fn index_rendered(paths: &Paths, private: bool) -> Result<Vec<Self>, ()> {
+    let mut result = Self::index_with_dates(paths, false, true)?;
+    result.retain(|repo| repo.private == private);
+
+    // the private store may not exist if repositories are only marked as
+    // private
+    if private && paths.private_store.is_dir() {
+      result.extend(Self::index_with_dates(paths, true, true)?);
+      Self::sort(&mut result);
+    }
+
+    Ok(result)
+  }
+
+  /// Sorts `repos` in the order of the index of projects, according to
+  /// `site.repo_sort`
+  fn sort(repos: &mut [Self]) {
+    match config::REPO_SORT {
+      RepoSort::Created => repos.sort_by_key(|r| cmp::Reverse(r.first_commit)),
+      RepoSort::Updated => repos.sort_by_key(|r| cmp::Reverse(r.last_commit.seconds())),
+      RepoSort::Name    => repos.sort_by(|r1, r2| {
+        r1.name.to_lowercase()
+          .cmp(&r2.name.to_lowercase())
+          .then_with(|| r1.name.cmp(&r2.name))
+      }),
+    }
+  }
+
+  fn index_with_dates(
+    paths: &Paths,
+    private: bool,
+    rendered_dates: bool,
+  ) -> Result<Vec<Self>, ()> {
+    let repos_dir = paths.repos_dir(private);
+
+    match fs::read_dir(repos_dir) {
+      Ok(dir) => {
+        let mut result = Vec::new();
+        for entry in dir.flatten() {
+          match entry.file_type() {
+            Ok(ft) if ft.is_dir() => {
+              let repo_path = entry.path();
+              let repo_name = entry.file_name();
+
+              // a single broken repository shouldn't prevent us from
+              // rendering all others
+              let repo = RepoInfo::open_with_dates(
+                repo_path,
+                repo_name.to_string_lossy(),
+                private,
+                rendered_dates.then_some(paths),
+              );
+              match repo {
+                Ok(repo) => result.push(repo),
+                Err(())  => {
+                  warnln!("Skipping {repo_name:?}");
+                }
+              }
+            }
+            _ => continue,
+          }
+        }
+
+        Self::sort(&mut result);
+
+        Ok(result)
+      }
+      Err(e) => {
+        errorln!("Could not read repositories in {repos_dir:?}: {e}");
+        Err(())
+      }
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReadmeFormat {
+  Txt,
+  Md,
+  /// Markup languages we display as plain text, since we can't render them
+  Rst,
+  Org,
+  AsciiDoc,
+}
+
+impl ReadmeFormat {
+  fn from_name(name: &str) -> Self {
+    match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+      Some("md" | "markdown") => Self::Md,
+      Some("rst")             => Self::Rst,
+      Some("org")             => Self::Org,
+      Some("adoc")            => Self::AsciiDoc,
+      _                       => Self::Txt,
+    }
+  }
+
+  /// The name of the markup language, for formats we don't render
+  fn unrendered_name(self) -> Option<&'static str> {
+    match self {
+      Self::Txt | Self::Md => None,
+      Self::Rst            => Some("reStructuredText"),
+      Self::Org            => Some("Org"),
+      Self::AsciiDoc       => Some("AsciiDoc"),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+struct Readme {
+  content: String,
+  format:  ReadmeFormat,
+}
+
+impl Readme {
+  /// Looks for a README file among the entries of `tree`
+  ///
+  /// If there are multiple README files, the one listed first in
+  /// [`config::README_NAMES`] is picked.
+  fn find(repo: &Repository, tree: &Tree<'_>) -> Option<Self> {
+    let mut candidates = Vec::new();
+    for entry in tree.iter() {
+      let (Some(ObjectType::Blob), Some(name)) = (entry.kind(), entry.name())
+      else {
+        continue;
+      };
+
+      if let Some(priority) = config::README_NAMES.iter().position(|n| *n == name) {
+        candidates.push((priority, entry));
+      }
+    }
+    candidates.sort_by_key(|(priority, _)| *priority);
+
+    if candidates.len() > 1 {
+      let names: Vec<_> = candidates
+        .iter()
+        .map(|(_, entry)| entry.name().unwrap())
+        .collect();
+      warnln!("Multiple README files encountered: {names:?}. Using the first non-binary one");
+    }
+
+    for (_, entry) in candidates {
+      let name = entry.name().unwrap();
+      let blob = entry
+        .to_object(repo)
+        .unwrap()
+        .peel_to_blob()
+        .unwrap();
+
+      if blob.is_binary() {
+        warnln!("README file {name:?} is binary. Ignoring {name:?}");
+        continue;
+      }
+
+      // text files aren't garanteed to be valid UTF-8
+      let content = String::from_utf8_lossy(blob.content()).into_owned();
+
+      let format = ReadmeFormat::from_name(name);
+
+      return Some(Readme { content, format, });
+    }
+
+    None
+  }
+
+  /// `location` is the location of the README in the repository, against
+  /// which the relative URLs of its images are resolved
+  fn render(
+    &self,
+    f: &mut OutputFile,
+    location: markdown::Location<'_>,
+  ) -> io::Result<()> {
+    writeln!(f, "<section id=\"readme\">")?;
+    if self.format == ReadmeFormat::Md {
+      markdown::render_html(
+        f,
+        &self.content,
+        config::README_HEADING_OFFSET,
+        location,
+        None,
+      )?;
+    } else {
+      if let Some(language) = self.format.unrendered_name() {
+        writeln!(f, "<p class=\"notice\">This README is written in {language}, which is not rendered: it is displayed as plain text.</p>")?;
+      }
+      writeln!(f, "<pre>{content}</pre>", content = Escaped(&self.content))?;
+    }
+    writeln!(f, "</section>")?;
+
+    Ok(())
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SpecialDoc {
+  /// The name of the file at the root of the repository
+  name:   &'static str,
+  /// The name of the output page
+  page:   &'static str,
+  /// The label of the page in the navigation bar
+  label:  &'static str,
+  format: ReadmeFormat,
+}
+
+#[derive(Clone, Debug)]
+struct Doc {
+  content: String,
+  kind:    &'static SpecialDoc,
+}
+
+/// Renders the pages of a single repository
+pub struct RepoRenderer<'repo> {
+  name:        &'repo str,
+  owner:       &'repo str,
+  description: Option<&'repo str>,
+  clone_url:   &'repo str,
+
+  last_commit:  Time,
+  first_commit: u32,
+
+  repo:    &'repo Repository,
+  head:    Tree<'repo>,
+  head_id: Oid,
+  branch:  String,
+
+  // the attributes of the files of the rendered commit, used to hide the
+  // ones marked with export-ignore
+  attributes: Option<Attributes>,
+
+  // the branch rendered at the top-level of the repository's output directory
+  main_branch: String,
+  // the names of all local branches, for the branch switcher
+  branches:    Vec<String>,
+  // "" for the main branch and "branches/{branch}/" otherwise
+  branch_root: String,
+
+  readme: Option<Readme>,
+  docs:   Vec<Doc>,
+
+  // the state of the last render of the repository, which the tree of the
+  // rendered branch at the time is compared to in order to skip rendering
+  // pages of files which haven't changed
+  //
+  // None if running with --full-build enabled
+  previous_state: Option<Rc<State>>,
+  previous_tree:  Option<Tree<'repo>>,
+
+  // stores the seconds since the Unix epoch and the id of the last commit
+  // each path was modified at, which are listed in the tree pages
+  last_commits: RefCell<HashMap<PathBuf, (i64, Oid)>>,
+
+  // the commits which have a page of their own, which may not be all of them
+  // if log.max_commits is set
+  commit_pages: RefCell<HashSet<Oid>>,
+
+  // the commits whose pages were written by the renderer of any branch: the
+  // main branch is rendered first, so that the pages of the commits in it
+  // link to its tree rather than to the tree of some other branch
+  written_commits: Rc<RefCell<HashSet<Oid>>>,
+
+  // the blobs in the rendered tree, whose deduplicated copies should be kept
+  // if output.dedup_blobs is set
+  blob_ids: RefCell<HashSet<Oid>>,
+
+  // the files changed by each commit in the log, which are cached in the
+  // state file
+  changed_paths: RefCell<HashMap<Oid, Vec<PathBuf>>>,
+
+  // the files whose pages were removed from the tree of any branch since the
+  // last render: the pages of the commits which changed them may link to
+  // them, so they are rendered again
+  //
+  // None if these couldn't be determined, in which case the pages of all
+  // commits are rendered again
+  removed_paths: Option<Rc<HashSet<PathBuf>>>,
+
+  // cached constants which depend on command-line flags:
+  // these shouldn't be modified at runtime
+  output_path: PathBuf,
+  output_root: &'static str,
+  // whether the repository is in the public store but marked as private
+  marked_private: bool,
+  gzip:        bool,
+  verbose:     bool,
+  jobs:        usize,
+
+  // the number of pages written and skipped, shared by all renderers
+  stats: &'repo Stats,
+
+  paths: &'repo Paths,
+}
+
+impl<'repo> RepoRenderer<'repo> {
+  pub fn new(
+    repo: &'repo RepoInfo,
+    paths: &'repo Paths,
+    flags: Flags,
+    branch: Option<&str>,
+    jobs: usize,
+    stats: &'repo Stats,
+  ) -> Result<Self, ()> {
+    let reference = match find_branch(&repo.repo, branch) {
+      Ok(reference) => reference,
+      Err(e) => {
+        if let Some(branch) = branch {
+          errorln!("Could not find branch {branch:?} in {name:?}: {e}",
+                   name = repo.name, e = e.message());
+        } else {
+          errorln!("Could not retrieve HEAD of {name:?}: {e}",
+                   name = repo.name);
+        }
+        return Err(());
+      }
+    };
+
+    let (branch, head_id, head) = resolve_branch(&reference);
+
+    let branches: Vec<String> = repo
+      .repo
+      .branches(Some(BranchType::Local))
+      .map(|branches| {
+        branches
+          .flatten()
+          .filter_map(|(b, _)| b.name().ok().flatten().map(String::from))
+          .collect()
+      })
+      .unwrap_or_default();
+
+    let readme = Readme::find(&repo.repo, &head);
+
+    let mut docs: Vec<Doc> = Vec::new();
+    for entry in head.iter() {
+      if let (Some(ObjectType::Blob), Some(name)) =
+             (entry.kind(), entry.name()) {
+        if let Some(kind) = SPECIAL_DOCS.iter().find(|d| d.name == name) {
+          if let Some(old) = docs.iter().find(|d| d.kind.page == kind.page) {
+            warnln!("Multiple {label} files encountered: {old_name:?} and {name:?}. Ignoring {name:?}",
+                    label = kind.label, old_name = old.kind.name);
+            continue;
+          }
+
+          let blob = entry
+            .to_object(&repo.repo)
+            .unwrap()
+            .peel_to_blob()
+            .unwrap();
+
+          if blob.is_binary() {
+            warnln!("{name} file is binary. Ignoring it");
+            continue;
+          }
+
+          // text files aren't garanteed to be valid UTF-8
+          let content = String::from_utf8_lossy(blob.content()).into_owned();
+
+          docs.push(Doc { content, kind, });
+        }
+      }
+    }
+
+    // keep the order of the navigation bar consistent
+    docs.sort_by_key(|doc| {
+      SPECIAL_DOCS.iter().position(|d| d.page == doc.kind.page)
+    });
+
+    let output_path = paths.output_dir(repo.private);
+    let output_root = if repo.private {
+      config::PRIVATE_OUTPUT_ROOT
+    } else {
+      ""
+    };
+
+    let previous_state = if flags.full_build() {
+      None
+    } else {
+      let mut path = output_path.clone();
+      path.push(&repo.name);
+      path.push(STATE_FILE);
+      Some(Rc::new(State::read(path)))
+    };
+    let previous_tree = previous_tree(&repo.repo, previous_state.as_deref(), &branch);
+    let removed_paths = match previous_state {
+      Some(ref state) => removed_paths(&repo.repo, state, &branches),
+      None            => Some(HashSet::new()),
+    };
+
+    Ok(Self {
+      name: &repo.name,
+      owner: &repo.owner,
+      description: repo.description.as_deref(),
+      clone_url: &repo.clone_url,
+
+      last_commit: repo.last_commit,
+      first_commit: repo.first_commit,
+
+      repo: &repo.repo,
+      head,
+      head_id,
+      attributes: open_attributes(&repo.repo, &repo.name, head_id),
+      main_branch: branch.clone(),
+      branch,
+      branches,
+      branch_root: String::new(),
+
+      readme,
+      docs,
+
+      previous_state,
+      previous_tree,
+      last_commits: RefCell::default(),
+      commit_pages: RefCell::default(),
+      written_commits: Rc::default(),
+      blob_ids: RefCell::default(),
+      changed_paths: RefCell::default(),
+      removed_paths: removed_paths.map(Rc::new),
+      output_path,
+      output_root,
+      marked_private: repo.private && !flags.private(),
+      gzip: flags.gzip(),
+      verbose: flags.verbose(),
+      jobs,
+      stats,
+      paths,
+    })
+  }
+
+  pub fn render(&self) -> io::Result<()> {
+    // a repository in the public store which was marked as private after its
+    // pages were rendered shouldn't be left in the public output root
+    if self.marked_private {
+      self.remove_public_pages()?;
+    }
+
+    self.render_summary()?;
+    self.render_json()?;
+    self.render_log()?;
+    for doc in &self.docs {
+      self.render_doc(doc)?;
+    }
+    self.render_tree()?;
+
+    let mut state = State::default();
+    state.set_head(&self.branch, self.head_id);
+    state.set_header(&self.branch, self.header_hash());
+
+    for branch in &self.branches {
+      if *branch == self.main_branch {
+        continue;
+      }
+
+      let renderer = self.branch_renderer(branch)?;
+      renderer.render_log()?;
+      renderer.render_tree()?;
+      state.set_head(branch, renderer.head_id);
+      state.set_header(branch, renderer.header_hash());
+      self.blob_ids.borrow_mut().extend(renderer.blob_ids.take());
+      self.changed_paths.borrow_mut().extend(renderer.changed_paths.take());
+    }
+
+    for (id, paths) in self.changed_paths.take() {
+      state.set_changed_paths(id, paths);
+    }
+
+    self.remove_stale_branches()?;
+
+    // the deduplicated blobs are shared by all branches, so they can only be
+    // cleaned up once all of them are rendered
+    if config::DEDUP_BLOBS == DedupBlobs::Off {
+      self.remove_blobs_dir()?;
+    } else if self.previous_state.is_some() {
+      self.remove_stale_blobs()?;
+    }
+
+    // only record the new state once all pages are rendered, so that pages
+    // are rendered again if we fail midway
+    let mut path = self.output_path.clone();
+    path.push(self.name);
+    path.push(STATE_FILE);
+    state.write(create_output(path)?)
+  }
+
+  /// Removes the pages of the repository in the public output root
+  fn remove_public_pages(&self) -> io::Result<()> {
+    let mut path = self.paths.output_dir(false);
+    path.push(self.name);
+    if !path.is_dir() {
+      return Ok(());
+    }
+
+    if is_dry_run() {
+      infoln!("Would remove the public pages of private repository {path:?}");
+      return Ok(());
+    }
+
+    infoln!("Removing the public pages of private repository {path:?}");
+    if let Err(e) = fs::remove_dir_all(&path) {
+      errorln!("Failed to remove {path:?}: {e}");
+      return Err(with_path(e, "remove", &path));
+    }
+
+    Ok(())
+  }
+
+  /// Removes the pages of the branches which were deleted since the last
+  /// render, or which are now rendered at the top-level of the repository
+  fn remove_stale_branches(&self) -> io::Result<()> {
+    let mut path = self.output_path.clone();
+    path.push(self.name);
+    path.push(BRANCHES_DIR);
+
+    let branches: Vec<&str> = self
+      .branches
+      .iter()
+      .filter(|branch| **branch != self.main_branch)
+      .map(String::as_str)
+      .collect();
+    remove_stale_branch_dirs(&path, "", &branches)
+  }
+
+  /// Removes the deduplicated copies of the blobs left over from when
+  /// `output.dedup_blobs` was set
+  fn remove_blobs_dir(&self) -> io::Result<()> {
+    let mut objects_path = self.output_path.clone();
+    objects_path.push(self.name);
+    objects_path.push(BLOBS_DIR);
+    if !objects_path.is_dir() {
+      return Ok(());
+    }
+
+    if is_dry_run() {
+      infoln!("Would remove the deduplicated blobs at {objects_path:?}");
+      return Ok(());
+    }
+
+    infoln!("Removing the deduplicated blobs at {objects_path:?}");
+    if let Err(e) = fs::remove_dir_all(&objects_path) {
+      errorln!("Failed to remove {objects_path:?}: {e}");
+      return Err(with_path(e, "remove", &objects_path));
+    }
+
+    Ok(())
+  }
+
+  /// Removes the deduplicated copies of the blobs which aren't in any of the
+  /// rendered trees anymore
+  fn remove_stale_blobs(&self) -> io::Result<()> {
+    let mut objects_path = self.output_path.clone();
+    objects_path.push(self.name);
+    objects_path.push(BLOBS_DIR);
+
+    let entries = match fs::read_dir(&objects_path) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+      Err(e) => {
+        errorln!("Could not read {objects_path:?}: {e}");
+        return Err(with_path(e, "read", &objects_path));
+      }
+    };
+
+    let blob_ids = self.blob_ids.borrow();
+    for entry in entries.flatten() {
+      let is_stale = entry
+        .file_name()
+        .to_str()
+        .and_then(|name| Oid::from_str(name).ok())
+        .is_none_or(|id| !blob_ids.contains(&id));
+      if !is_stale {
+        continue;
+      }
+
+      let path = entry.path();
+      if is_dry_run() {
+        infoln!("Would remove stale blob {path:?}");
+        continue;
+      }
+
+      infoln!("Removing stale blob {path:?}");
+      if let Err(e) = fs::remove_file(&path) {
+        errorln!("Failed to remove {path:?}: {e}");
+        return Err(with_path(e, "remove", &path));
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Returns a renderer for the log and tree pages of another branch of the
+  /// repository, which are rendered at `{name}/branches/{branch}/`
+  fn branch_renderer(&self, branch: &str) -> io::Result<Self> {
+    let reference = self
+      .repo
+      .find_branch(branch, BranchType::Local)
+      .expect("branch should be listed in the repository")
+      .into_reference();
+    let (branch, head_id, head) = resolve_branch(&reference);
+
+    let branch_root = format!("{BRANCHES_DIR}/{branch}/");
+    let previous_tree = previous_tree(self.repo, self.previous_state.as_deref(), &branch);
+
+    let mut path = self.output_path.clone();
+    path.push(self.name);
+    for component in Path::new(&branch_root).components() {
+      path.push(component);
+      if !path.is_dir() { create_dir(&path)?; }
+    }
+
+    Ok(Self {
+      name: self.name,
+      owner: self.owner,
+      description: self.description,
+      clone_url: self.clone_url,
+
+      last_commit: self.last_commit,
+      first_commit: self.first_commit,
+
+      repo: self.repo,
+      head,
+      head_id,
+      attributes: open_attributes(self.repo, self.name, head_id),
+      branch,
+
+      main_branch: self.main_branch.clone(),
+      branches: self.branches.clone(),
+      branch_root,
+
+      readme: self.readme.clone(),
+      docs: self.docs.clone(),
+
+      previous_tree,
+      previous_state: self.previous_state.clone(),
+      last_commits: RefCell::default(),
+      commit_pages: RefCell::default(),
+      written_commits: Rc::clone(&self.written_commits),
+      blob_ids: RefCell::default(),
+      changed_paths: RefCell::default(),
+      removed_paths: self.removed_paths.clone(),
+      output_path: self.output_path.clone(),
+      output_root: self.output_root,
+      marked_private: self.marked_private,
+      gzip: self.gzip,
+      verbose: self.verbose,
+      jobs: self.jobs,
+      stats: self.stats,
+      paths: self.paths,
+    })
+  }
+
+  /// The URL of the raw blobs of the rendered branch
+  fn blob_root(&self) -> String {
+    format!("/{root}{name}/{branch_root}{BLOB_SUBDIR}",
+            root = self.output_root,
+            name = EscapedPath(self.name),
+            branch_root = EscapedPath(&self.branch_root))
+  }
+
+  /// The URL of the page of the blob at `path` in the tree of the rendered
+  /// branch, unless there's no such page
+  fn blob_page_url(&self, path: &Path) -> Option<String> {
+    if !has_blob_page(&self.head, self.attributes.as_ref(), path) {
+      return None;
+    }
+
+    Some(format!("/{root}{name}/{branch_root}{TREE_SUBDIR}/{page}",
+                 root = self.output_root,
+                 name = EscapedPath(self.name),
+                 branch_root = EscapedPath(&self.branch_root),
+                 page = EscapedPath(&blob_page_path(path))))
+  }
+
+  /// Prints the HTML preamble
+  fn render_header(
+    &self,
+    f: &mut OutputFile,
+    title: PageTitle<'repo>
+  ) -> io::Result<()> {
+    render_header(f, self.paths, title, self.description)?;
+    writeln!(f, "<main>")?;
+    writeln!(f, "<h1>{title}</h1>", title = Escaped(self.name))?;
+    if let Some(description) = self.description {
+      writeln!(f, "<p>\n{d}\n</p>", d = Escaped(description.trim()))?;
+    }
+    writeln!(f, "<nav>")?;
+    writeln!(f, "<ul>")?;
+    writeln!(f, "<li{class}><a href=\"/{root}{name}/index.html\">summary</a></li>",
+                root = self.output_root,
+                name = EscapedPath(self.name),
+                class = if matches!(title, PageTitle::Summary { .. }) { " class=\"nav-selected\"" } else { "" })?;
+    writeln!(f, "<li{class}><a href=\"/{root}{name}/{branch_root}{COMMIT_SUBDIR}/index.html\">log</a></li>",
+                root = self.output_root,
+                name = EscapedPath(self.name),
+                branch_root = EscapedPath(&self.branch_root),
+                class = if matches!(title, PageTitle::Log { .. } | PageTitle::Commit { .. }) { " class=\"nav-selected\"" } else { "" })?;
+    writeln!(f, "<li{class}><a href=\"/{root}{name}/{branch_root}{TREE_SUBDIR}/index.html\">tree</a></li>",
+                root = self.output_root,
+                name = EscapedPath(self.name),
+                branch_root = EscapedPath(&self.branch_root),
+                class = if matches!(title, PageTitle::TreeEntry { .. }) { " class=\"nav-selected\"" } else { "" })?;
+    for doc in &self.docs {
+      let selected = matches!(title, PageTitle::Doc { label, .. } if label == doc.kind.label);
+      writeln!(f, "<li{class}><a href=\"/{root}{name}/{page}\">{label}</a></li>",
+                  root = self.output_root,
+                  name = EscapedPath(self.name),
+                  page = doc.kind.page,
+                  label = doc.kind.label,
+                  class = if selected { " class=\"nav-selected\"" } else { "" })?;
+    }
+    writeln!(f, "</ul>")?;
+    writeln!(f, "</nav>")?;
+
+    if self.branches.len() > 1 {
+      writeln!(f, "<nav class=\"branches\">")?;
+      writeln!(f, "<ul>")?;
+      for branch in &self.branches {
+        let branch_root = if *branch == self.main_branch {
+          String::new()
+        } else {
+          format!("{BRANCHES_DIR}/{branch}/")
+        };
+
+        writeln!(f, "<li{class}><a href=\"/{root}{name}/{branch_root}{TREE_SUBDIR}/index.html\">{branch}</a></li>",
+                    root = self.output_root,
+                    name = EscapedPath(self.name),
+                    branch_root = EscapedPath(&branch_root),
+                    branch = Escaped(branch),
+                    class = if *branch == self.branch { " class=\"nav-selected\"" } else { "" })?;
+      }
+      writeln!(f, "</ul>")?;
+      writeln!(f, "</nav>")?;
+    }
+
+    Ok(())
+  }
+
+  pub fn render_tree(
+    &self,
+  ) -> io::Result<()> {
+    let mut tree_stack = Vec::new();
+    let mut blob_stack = Vec::new();
+
+    // the paths of the trees and blobs in the current tree, used to cleanup
+    // the pages of deleted files
+    let mut tree_paths = HashSet::new();
+    tree_paths.insert(PathBuf::new());
+
+    self.render_subtree(
+      &self.head, PathBuf::new(), true,
+      &mut tree_stack,
+      &mut blob_stack,
+    )?;
+
+    while let Some((tree, path)) = tree_stack.pop() {
+      tree_paths.insert(path.clone());
+      self.render_subtree(
+        &tree, path, false,
+        &mut tree_stack,
+        &mut blob_stack,
+      )?;
+    }
+
+    let blob_paths: HashSet<PathBuf> = blob_stack
+      .iter()
+      .map(|(_, path)| path.clone())
+      .collect();
+    self.blob_ids.borrow_mut().extend(blob_stack.iter().map(|(blob, _)| blob.id));
+
+    self.render_blobs(blob_stack)?;
+
+    // when running with --full-build we leave the output directory alone:
+    // the user may remove it altogether before running a full build
+    if self.previous_state.is_some() {
+      let mut pages_path = self.output_path.clone();
+      pages_path.push(self.name);
+      pages_path.push(&self.branch_root);
+
+      let mut raw_blobs_path = pages_path.clone();
+      pages_path.push(TREE_SUBDIR);
+      raw_blobs_path.push(BLOB_SUBDIR);
+
+      self.remove_stale_files(
+        &pages_path, Path::new(""), &tree_paths, &blob_paths, true,
+      )?;
+      self.remove_stale_files(
+        &raw_blobs_path, Path::new(""), &tree_paths, &blob_paths, false,
+      )?;
+    }
+
+    Ok(())
+  }
+
+  /// Removes the files in `dir` which do not correspond to an entry of the
+  /// tree anymore, e.g. because the file was deleted from the repository
+  ///
+  /// `dir` should be the output directory of the tree at `parent`. If `pages`
+  /// is set `dir` is assumed to contain the HTML pages of the tree: otherwise
+  /// it is assumed to contain the raw blobs.
+  fn remove_stale_files(
+    &self,
+    dir: &Path,
+    parent: &Path,
+    tree_paths: &HashSet<PathBuf>,
+    blob_paths: &HashSet<PathBuf>,
+    pages: bool,
+  ) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+      Ok(entries) => entries,
+      // the directory would have been created if not for --dry-run
+      Err(e) if e.kind() == io::ErrorKind::NotFound && is_dry_run() => {
+        return Ok(());
+      }
+      Err(e) => {
+        errorln!("Could not read {dir:?}: {e}");
+        return Err(with_path(e, "read", dir));
+      }
+    };
+
+    for entry in entries.flatten() {
+      let output_path = entry.path();
+      let file_name = entry.file_name();
+
+      if entry.file_type().is_ok_and(|ft| ft.is_dir()) {
+        let path = parent.join(&file_name);
+
+        if tree_paths.contains(&path) {
+          self.remove_stale_files(
+            &output_path, &path, tree_paths, blob_paths, pages,
+          )?;
+        } else {
+          if is_dry_run() {
+            infoln!("Would remove stale directory {output_path:?}");
+            continue;
+          }
+
+          infoln!("Removing stale directory {output_path:?}");
+          if let Err(e) = fs::remove_dir_all(&output_path) {
+            errorln!("Failed to remove {output_path:?}: {e}");
+            return Err(with_path(e, "remove", &output_path));
+          }
+        }
+
+        continue;
+      }
+
+      let is_stale = if pages {
+        let file_name = file_name.to_string_lossy();
+        let file_name = file_name.strip_suffix(".gz").unwrap_or(&file_name);
+
+        match file_name.strip_suffix(".html") {
+          Some("index") => false,
+          Some(page)    => match blob_name_of_page(page) {
+            Some(blob_name) => !blob_paths.contains(&parent.join(blob_name)),
+            None            => true, // a page we wouldn't render anymore
+          },
+          None          => false, // not one of our pages
+        }
+      } else {
+        !blob_paths.contains(&parent.join(&file_name))
+      };
+
+      if is_stale && is_dry_run() {
+        infoln!("Would remove stale file {output_path:?}");
+      } else if is_stale {
+        infoln!("Removing stale file {output_path:?}");
+        if let Err(e) = fs::remove_file(&output_path) {
+          errorln!("Failed to remove {output_path:?}: {e}");
+          return Err(with_path(e, "remove", &output_path));
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn render_subtree(
+    &'repo self,
+    tree: &Tree<'repo>,
+    parent: PathBuf,
+    root: bool,
+    tree_stack: &mut Vec<(Tree<'repo>, PathBuf)>,
+    blob_stack: &mut Vec<(Blob, PathBuf)>,
+  ) -> io::Result<()> {
+    let mut blobs_path = self.output_path.clone();
+    blobs_path.push(self.name);
+    blobs_path.push(&self.branch_root);
+    blobs_path.push(BLOB_SUBDIR);
+    blobs_path.extend(&parent);
+
+    if !blobs_path.is_dir() {
+      create_dir(&blobs_path)?;
+    }
+
+    let mut index_path = self.output_path.clone();
+    index_path.push(self.name);
+    index_path.push(&self.branch_root);
+    index_path.push(TREE_SUBDIR);
+    index_path.extend(&parent);
+
+    if !index_path.is_dir() {
+      create_dir(&index_path)?;
+    }
+
+    // ========================================================================
+    // collect the entries to be listed and queue the subtrees and blobs for
+    // rendering
+    let mut entries = Vec::new();
+    for entry in tree.iter() {
+      let name = entry.name().unwrap();
+      let mut path = parent.clone();
+      path.push(name);
+
+      // entries hidden with export-ignore are neither listed nor rendered
+      if self.is_export_ignored(&path) {
+        continue;
+      }
+
+      match entry.kind() {
+        Some(ObjectType::Blob) if Mode(entry.filemode()).is_symlink() => {}
+        Some(ObjectType::Blob) => {
+          blob_stack.push(
+            (Blob { id: entry.id(), mode: Mode(entry.filemode()) }, path.clone())
+          );
+        }
+        Some(ObjectType::Tree) => {
+          let subtree = entry
+            .to_object(self.repo)
+            .unwrap()
+            .peel_to_tree()
+            .unwrap();
+
+          tree_stack.push((subtree, path.clone()));
+        }
+        Some(ObjectType::Commit) => {}
+        Some(kind) => {
+          unreachable!("unexpected tree entry kind {kind:?}")
+        }
+        None => unreachable!("couldn't get tree entry kind"),
+      }
+
+      // hidden dotfiles are still rendered, so that links to them keep
+      // working, but they aren't listed
+      if config::HIDDEN_DOTFILES.contains(&name) {
+        continue;
+      }
+
+      entries.push((entry.to_owned(), path));
+    }
+
+    // list directories (and submodules) before files, each in alphabetical
+    // order, rather than in the order libgit2 gives us
+    entries.sort_by_cached_key(|(entry, _)| {
+      let is_file = !matches!(
+        entry.kind(),
+        Some(ObjectType::Tree) | Some(ObjectType::Commit),
+      );
+      (is_file, entry.name_bytes().to_vec())
+    });
+
+    // ========================================================================
+    // the page is rendered even if the tree hasn't changed since the last
+    // render: the last commits listed, the commits which have pages and the
+    // header may all have changed
+    index_path.push("index.html");
+
+    let mut f = create_html_file(index_path, self.gzip)?;
+
+    self.render_header(
+      &mut f,
+      PageTitle::TreeEntry { repo_name: self.name, path: &parent },
+    )?;
+    writeln!(&mut f, "<div class=\"table-container\">")?;
+    writeln!(&mut f, "<table>")?;
+    writeln!(&mut f, "<thead><tr><td>Name</td><td>Last commit</td><td align=\"right\">Date</td></tr></thead>")?;
+    writeln!(&mut f, "<tbody>")?;
+
+    if !root {
+      writeln!(
+        &mut f,
+        "<tr><td><a href=\"..\" class=\"subtree\">..</a></td><td></td><td></td></tr>",
+      )?;
+    }
+
+    // write the table rows
+    for (entry, path) in &entries {
+      match entry.kind() {
+        Some(ObjectType::Blob) if Mode(entry.filemode()).is_symlink() => {
+          // the content of a symlink blob is the path of its target
+          let blob = entry
+            .to_object(self.repo)
+            .unwrap()
+            .peel_to_blob()
+            .unwrap();
+          let target = String::from_utf8_lossy(blob.content());
+
+          writeln!(
+            &mut f,
+            "<tr><td><span class=\"symlink\">{path} -&gt; {target}</span></td>",
+            path = Escaped(&path.to_string_lossy()),
+            target = Escaped(&target),
+          )?;
+        }
+        Some(ObjectType::Blob) => {
+          writeln!(
+            &mut f,
+            "<tr><td><a href=\"/{root}{name}/{branch_root}{TREE_SUBDIR}/{page}\">{path}</a></td>",
+            root = self.output_root,
+            name = EscapedPath(self.name),
+            branch_root = EscapedPath(&self.branch_root),
+            page = EscapedPath(&blob_page_path(path)),
+            path = Escaped(&path.to_string_lossy()),
+          )?;
+        }
+        Some(ObjectType::Tree) => {
+          writeln!(
+            &mut f,
+            "<tr><td><a href=\"/{root}{name}/{branch_root}{TREE_SUBDIR}/{url}/index.html\" class=\"subtree\">{path}/</a></td>",
+            root = self.output_root,
+            name = EscapedPath(self.name),
+            branch_root = EscapedPath(&self.branch_root),
+            url = EscapedPath(&path.to_string_lossy()),
+            path = Escaped(&path.to_string_lossy()),
+          )?;
+        }
+        Some(ObjectType::Commit) => {
+          // the id of a gitlink is the commit the submodule is pinned to,
+          // which is not in our object database
+          let id = entry.id();
+          let shorthand_id = short_id(None, id);
+
+          let url = if !self.repo.is_bare() {
+            let submod = self
+              .repo
+              .find_submodule(&path.to_string_lossy())
+              .unwrap();
+            submod.url().map(String::from)
+          } else {
+            // we cannot lookup a submodule in a bare repo, because the
+            // .gitmodules index is located in the working tree
+            warnln!("Cannot lookup the {path:?} submodule in {repo}: {repo:?} is a bare repository",
+                    repo = self.name);
+            None
+          };
+
+          write!(&mut f, "<tr><td>")?;
+          if let Some(ref url) = url {
+            write!(
+              &mut f,
+              "<a href=\"{url}\" class=\"subtree\">{path}@</a>",
+              url = EscapedUrl(url),
+              path = Escaped(&path.to_string_lossy()),
+            )?;
+          } else {
+            write!(
+              &mut f,
+              "<span class=\"subtree\">{path}@</span>",
+              path = Escaped(&path.to_string_lossy()),
+            )?;
+          }
+
+          if let Some(commit_url) = url.and_then(|url| hosted_commit_url(&url, id)) {
+            writeln!(
+              &mut f,
+              "<a href=\"{url}\" class=\"submodule-id\">{shorthand_id}</a></td>",
+              url = EscapedUrl(&commit_url),
+            )?;
+          } else {
+            writeln!(&mut f, "<span class=\"submodule-id\">{shorthand_id}</span></td>")?;
+          }
+        }
+        _ => unreachable!("other entry kinds should have been filtered out"),
+      }
+
+      self.render_last_commit(&mut f, path)?;
+      writeln!(&mut f, "</tr>")?;
+    }
+
+    writeln!(&mut f, "</tbody>")?;
+    writeln!(&mut f, "</table>")?;
+    writeln!(&mut f, "</div>")?;
+
+    // the README at the root of the repository was already found when setting
+    // up the renderer
+    let subtree_readme;
+    let readme = if root {
+      self.readme.as_ref()
+    } else {
+      subtree_readme = Readme::find(self.repo, tree);
+      subtree_readme.as_ref()
+    };
+    if let Some(readme) = readme {
+      let blob_root = self.blob_root();
+      let dir = parent.to_string_lossy();
+      readme.render(&mut f, markdown::Location { blob_root: &blob_root, dir: &dir })?;
+    }
+
+    writeln!(&mut f, "</main>")?;
+    render_footer(&mut f)?;
+    writeln!(&mut f, "</body>")?;
+    writeln!(&mut f, "</html>")?;
+    f.finish()?;
+
+    Ok(())
+  }
+
+  /// Prints the table cells with the summary and the date of the last commit
+  /// which modified `path`
+  fn render_last_commit(
+    &self,
+    f: &mut OutputFile,
+    path: &Path,
+  ) -> io::Result<()> {
+    let last_commit = self
+      .last_commits
+      .borrow()
+      .get(path)
+      .and_then(|(_, id)| self.repo.find_commit(*id).ok());
+    let Some(commit) = last_commit else {
+      return writeln!(f, "<td></td><td></td>");
+    };
+
+    let time = commit.author().when();
+    let summary = Escaped(commit.summary().unwrap_or(""));
+    if self.has_commit_page(commit.id()) {
+      writeln!(
+        f,
+        "<td><a href=\"/{root}{name}/{COMMIT_SUBDIR}/{id}.html\">{summary}</a></td>",
+        root = self.output_root,
+        name = EscapedPath(self.name),
+        id = commit.id(),
+      )?;
+    } else {
+      writeln!(f, "<td>{summary}</td>")?;
+    }
+    writeln!(f, "<td align=\"right\"><time datetime=\"{datetime}\">{date}</time></td>",
+                datetime = DateTime(time),
+                date     = Date(time))
+  }
+
+  /// Hashes the inputs of [`Self::render_header`] which may change between
+  /// runs: the description, the docs and the branches of the repository
+  fn header_hash(&self) -> String {
+    let mut hasher = Sha256::default();
+
+    // the inputs are separated by NUL bytes, which none of them may contain
+    let mut input = |s: &str| {
+      hasher.update(s.as_bytes());
+      hasher.update(b"\0");
+    };
+    input(self.name);
+    input(self.description.unwrap_or_default());
+    input(self.output_root);
+    input(&self.branch);
+    input(&self.main_branch);
+    for doc in &self.docs {
+      input(doc.kind.page);
+    }
+    input("");
+    for branch in &self.branches {
+      input(branch);
+    }
+
+    hasher.finish()
+  }
+
+  /// Checks if the object at `path` is the same as when the repository was
+  /// last rendered and its page at `page_path` exists, in which case the page
+  /// doesn't need to be re-rendered
+  ///
+  /// Always returns `false` if running with `--full-build` enabled.
+  fn is_up_to_date<P: AsRef<Path>>(
+    &self,
+    page_path: P,
+    path: &Path,
+    id: Oid,
+  ) -> bool {
+    let Some(ref previous_tree) = self.previous_tree else {
+      return false;
+    };
+
+    let previous_id = previous_tree.get_path(path).ok().map(|entry| entry.id());
+
+    previous_id == Some(id) && page_path.as_ref().exists()
+  }
+
+  /// Checks if `path` is marked with the `export-ignore` attribute in the
+  /// repository's `.gitattributes` files
+  fn is_export_ignored(&self, path: &Path) -> bool {
+    self
+      .attributes
+      .as_ref()
+      .is_some_and(|attributes| attributes.is_set(path, "export-ignore"))
+  }
+
+  /// Renders the pages of `blobs` on up to `self.jobs` threads
+  ///
+  /// Git repositories can't be shared between threads, so each thread opens
+  /// the repository on its own. The headers of the pages are rendered
+  /// beforehand, since they depend on the state of the renderer.
+  fn render_blobs(&self, blobs: Vec<(Blob, PathBuf)>) -> io::Result<()> {
+    let mut objects_path = self.output_path.clone();
+    objects_path.push(self.name);
+    objects_path.push(BLOBS_DIR);
+
+    // raw files linked to the deduplicated blobs from when output.dedup_blobs
+    // was set should all be replaced by copies before the deduplicated blobs
+    // are removed
+    let relink = config::DEDUP_BLOBS == DedupBlobs::Off && objects_path.is_dir();
+
+    // the pages of unchanged blobs are rendered again if their header changed
+    let header_changed = self
+      .previous_state
+      .as_ref()
+      .and_then(|state| state.header(&self.branch))
+      != Some(self.header_hash().as_str());
+
+    let mut pages = Vec::new();
+    let mut objects = HashSet::new();
+    for (blob, path) in blobs {
+      let mut page_path = self.output_path.clone();
+      page_path.push(self.name);
+      page_path.push(&self.branch_root);
+      page_path.push(TREE_SUBDIR);
+      page_path.push(blob_page_path(&path));
+
+      // skip rendering the page if the blob hasn't changed since the last
+      // render
+      if !relink && !header_changed && self.is_up_to_date(&page_path, &path, blob.id) {
+        Stats::incr(&self.stats.blobs_skipped);
+        continue;
+      }
+      Stats::incr(&self.stats.blobs_written);
+
+      let mut f = create_html_file(&page_path, self.gzip)?;
+      self.render_header(
+        &mut f,
+        PageTitle::TreeEntry { repo_name: self.name, path: &path },
+      )?;
+
+      // identical blobs are only written once, by a single thread
+      let write_object = objects.insert(blob.id);
+      pages.push(BlobPage { f, blob, path, write_object });
+    }
+
+    let mut raw_blobs_path = self.output_path.clone();
+    raw_blobs_path.push(self.name);
+    raw_blobs_path.push(&self.branch_root);
+    raw_blobs_path.push(BLOB_SUBDIR);
+
+    if config::DEDUP_BLOBS != DedupBlobs::Off && !objects_path.is_dir() {
+      create_dir(&objects_path)?;
+    }
+
+    // the raw blobs are linked to their copy in objects_path by relative
+    // symlinks, so that the output directory can be moved around
+    let depth = Path::new(&self.branch_root).components().count()
+      + Path::new(BLOB_SUBDIR).components().count();
+    let objects_link = format!("{}{BLOBS_DIR}", "../".repeat(depth));
+
+    let ctx = BlobContext {
+      blob_root:      self.blob_root(),
+      raw_blobs_path,
+      objects_path,
+      objects_link,
+      verbose:        self.verbose,
+    };
+
+    let jobs = cmp::min(self.jobs, pages.len());
+    if jobs <= 1 {
+      let mut links = Vec::new();
+      for page in pages {
+        links.extend(render_blob_page(self.repo, page, &ctx)?);
+      }
+      return ctx.link_blobs(links);
+    }
+
+    let repo_path = self.repo.path();
+    let queue = Mutex::new(pages.into_iter());
+    thread::scope(|s| {
+      let workers: Vec<_> = (0..jobs)
+        .map(|_| s.spawn(|| {
+          let repo = match Repository::open(repo_path) {
+            Ok(repo) => repo,
+            Err(e) => {
+              errorln!("Could not open {repo_path:?}: {e}", e = e.message());
+              return Err(io::Error::other(e));
+            }
+          };
+
+          // the lock is released before rendering the page
+          let mut links = Vec::new();
+          while let Some(page) = queue.lock().unwrap().next() {
+            links.extend(render_blob_page(&repo, page, &ctx)?);
+          }
+
+          Ok(links)
+        }))
+        .collect();
+
+      // the blobs are only linked to once all of them are written, since the
+      // blob a raw file is linked to may be written by another thread
+      let mut links = Vec::new();
+      for worker in workers {
+        links.extend(worker.join().expect("worker thread panicked")?);
+      }
+      ctx.link_blobs(links)
+    })
+  }
+
+  fn render_log(&self) -> io::Result<()> {
+    let mut revwalk = self.repo.revwalk().unwrap();
+    revwalk.push(self.head_id).unwrap();
+    let mut commits = Vec::new();
+
+    for oid in revwalk.flatten() {
+      let commit = self
+        .repo
+        .find_commit(oid)
+        .expect("we should be able to find the commit");
+
+      commits.push(commit);
+    }
+
+    let n_pages = config::MAX_COMMITS.map_or(commits.len(), |n| cmp::min(n, commits.len()));
+    self
+      .commit_pages
+      .borrow_mut()
+      .extend(commits[..n_pages].iter().map(|commit| commit.id()));
+
+    let listed = match config::OLDER_COMMITS {
+      OlderCommits::List => &commits[..],
+      OlderCommits::Omit => &commits[..n_pages],
+    };
+
+    // ========================================================================
+    let mut index_path = self.output_path.clone();
+    index_path.push(self.name);
+    index_path.push(&self.branch_root);
+    index_path.push(COMMIT_SUBDIR);
+
+    if !index_path.is_dir() {
+      create_dir(&index_path)?;
+    }
+
+    index_path.push("index.html");
+
+    let mut f = create_html_file(index_path, self.gzip)?;
+
+    self.render_header(&mut f, PageTitle::Log { repo_name: self.name })?;
+    // the search box is hidden unless JavaScript is enabled
+    writeln!(&mut f, "<input type=\"search\" id=\"search\" placeholder=\"Search commits...\" aria-label=\"Search commits\" hidden />")?;
+    writeln!(&mut f, "<script>")?;
+    writeln!(&mut f, "document.addEventListener(\"DOMContentLoaded\", function() {{")?;
+    writeln!(&mut f, "  var input = document.getElementById(\"search\");")?;
+    writeln!(&mut f, "  var articles = document.querySelectorAll(\".article-list article\");")?;
+    writeln!(&mut f, "  fetch(\"/{root}{name}/{branch_root}search.json\")",
+                     root = self.output_root,
+                     name = EscapedPath(self.name),
+                     branch_root = EscapedPath(&self.branch_root))?;
+    writeln!(&mut f, "    .then(function(r) {{ return r.json(); }})")?;
+    writeln!(&mut f, "    .then(function(commits) {{")?;
+    writeln!(&mut f, "      input.hidden = false;")?;
+    writeln!(&mut f, "      input.addEventListener(\"input\", function() {{")?;
+    writeln!(&mut f, "        var query = input.value.toLowerCase();")?;
+    writeln!(&mut f, "        commits.forEach(function(c, i) {{")?;
+    writeln!(&mut f, "          var text = (c.id + \" \" + c.summary + \" \" + c.author).toLowerCase();")?;
+    writeln!(&mut f, "          articles[i].hidden = text.indexOf(query) === -1;")?;
+    writeln!(&mut f, "        }});")?;
+    writeln!(&mut f, "      }});")?;
+    writeln!(&mut f, "    }});")?;
+    writeln!(&mut f, "}});")?;
+    writeln!(&mut f, "</script>")?;
+    writeln!(&mut f, "<div class=\"article-list\">")?;
+
+    let odb = self.repo.odb().ok();
+    for commit in listed {
+      let commit_sig = commit.author();
+
+      let author = commit_sig.name().unwrap();
+      let time = commit_sig.when();
+      let msg = commit
+        .summary()
+        .expect("commit summary should be valid UTF-8");
+
+      let id = commit.id();
+      let shorthand_id = short_id(odb.as_ref(), id);
+
+      writeln!(&mut f, "<article>")?;
+      writeln!(&mut f, "<div>")?;
+      if self.has_commit_page(id) {
+        writeln!(
+          &mut f,
+          "<span class=\"commit-heading\"><a href=\"/{root}{name}/{COMMIT_SUBDIR}/{id}.html\">{shorthand_id}</a> &mdash; {author}</span>",
+          root   = self.output_root,
+          name   = EscapedPath(self.name),
+          author = Escaped(author),
+        )?;
+      } else {
+        writeln!(
+          &mut f,
+          "<span class=\"commit-heading\">{shorthand_id} &mdash; {author}</span>",
+          author = Escaped(author),
+        )?;
+      }
+      writeln!(&mut f, "<time datetime=\"{datetime}\" title=\"{date}\">{relative}</time>",
+                       datetime = DateTime(time),
+                       date     = Date(time),
+                       relative = RelativeTime(time))?;
+      writeln!(&mut f, "</div>")?;
+      writeln!(&mut f, "<p>")?;
+      writeln!(&mut f, "{}", Escaped(msg))?;
+      writeln!(&mut f, "</p>")?;
+      writeln!(&mut f, "</article>")?;
+    }
+
+    writeln!(&mut f, "</div>")?;
+    writeln!(&mut f, "</main>")?;
+    render_footer(&mut f)?;
+    writeln!(&mut f, "</body>")?;
+    writeln!(&mut f, "</html>")?;
+    f.finish()?;
+
+    self.render_search_index(listed)?;
+
+    // commits without a page are still needed for the last commits of the
+    // files listed in the tree pages
+    for (i, commit) in commits.iter().enumerate() {
+      self.render_commit_and_collect_last_commit_times(commit, i < n_pages)?;
+    }
+
+    Ok(())
+  }
+
+  /// Renders a JSON index of the commits in the log to `search.json`, which
+  /// is used by the search box in the log page
+  ///
+  /// The entries of the index are in the same order as the log. Commits past
+  /// `log.max_commits` are marked as not having a page, so that they aren't
+  /// linked to.
+  fn render_search_index(&self, commits: &[Commit<'repo>]) -> io::Result<()> {
+    let mut path = self.output_path.clone();
+    path.push(self.name);
+    path.push(&self.branch_root);
+    path.push("search.json");
+
+    let mut f = create_output(path)?;
+
+    // ========================================================================
+    write!(&mut f, "[")?;
+    for (i, commit) in commits.iter().enumerate() {
+      let sig = commit.author();
+      let summary = commit
+        .summary()
+        .expect("commit summary should be valid UTF-8");
+
+      if i > 0 {
+        write!(&mut f, ",")?;
+      }
+      write!(
+        &mut f,
+        "{{\"id\":\"{id}\",\"summary\":\"{summary}\",\"author\":\"{author}\",\"date\":{date},\"page\":{page}}}",
+        id = commit.id(),
+        summary = JsonEscaped(summary),
+        author = JsonEscaped(sig.name().unwrap()),
+        date = sig.when().seconds(),
+        page = self.has_commit_page(commit.id()),
+      )?;
+    }
+    writeln!(&mut f, "]")?;
+
+    f.flush()
+  }
+
+  /// Collects the last commit each file and directory was modified at, given
+  /// the files changed by `commit`
+  fn collect_last_commit_times(&self, commit: &Commit<'repo>, changed_paths: Vec<PathBuf>) {
+    let mut last_commits = self.last_commits.borrow_mut();
+    let commit_time = commit.time().seconds();
+
+    for path in &changed_paths {
+      for path in path.ancestors() {
+        if path.as_os_str().is_empty() {
+          break;
+        }
+
+        // the newest commit is NOT garanteed by the order we loop through
+        // the commits
+        match last_commits.get_mut(path) {
+          Some((time, _)) if *time >= commit_time => {}
+          Some(last) => *last = (commit_time, commit.id()),
+          None => {
+            last_commits.insert(path.to_path_buf(), (commit_time, commit.id()));
+          }
+        }
+      }
+    }
+
+    self.changed_paths.borrow_mut().insert(commit.id(), changed_paths);
+  }
+
+  /// Whether the commit `id` has a page of its own, which can be linked to
+  fn has_commit_page(&self, id: Oid) -> bool {
+    self.commit_pages.borrow().contains(&id)
+  }
+
+  /// Renders the commit to HTML and updates the access time
+  ///
+  /// Shorcircutes if the commit page already exists, or if `render_page` is
+  /// `false`: in which case the files changed by the commit are read from the
+  /// state file if possible, rather than computing its diff.
+  fn render_commit_and_collect_last_commit_times(
+    &self,
+    commit: &Commit<'repo>,
+    render_page: bool,
+  ) -> io::Result<()> {
+    let mut path = self.output_path.clone();
+    path.push(self.name);
+    path.push(COMMIT_SUBDIR);
+    path.push(format!("{}.html", commit.id()));
+
+    // skip rendering the commit page if the file already exists, unless it
+    // may link to the page of a file which was removed since
+    let page_exists = self.previous_state.is_some() && path.exists();
+    let page_up_to_date = |changed_paths: &[PathBuf]| {
+      page_exists && self.removed_paths.as_ref().is_some_and(|removed| {
+        !changed_paths.iter().any(|path| removed.contains(path))
+      })
+    };
+
+    let cached_paths = self
+      .previous_state
+      .as_ref()
+      .and_then(|state| state.changed_paths(commit.id()));
+    if let Some(changed_paths) = cached_paths {
+      if !render_page || page_up_to_date(changed_paths) {
+        self.collect_last_commit_times(commit, changed_paths.to_vec());
+        if render_page {
+          Stats::incr(&self.stats.commits_skipped);
+        }
+        return Ok(());
+      }
+    }
+
+    // ========================================================================
+    let mut diff_opts = DiffOptions::new();
+    diff_opts
+      .context_lines(config::DIFF_CONTEXT_LINES)
+      .interhunk_lines(config::DIFF_INTERHUNK_LINES);
+
+    let mut diff = self
+      .repo
+      .diff_tree_to_tree(
+        commit.parent(0).and_then(|p| p.tree()).ok().as_ref(),
+        commit.tree().ok().as_ref(),
+        Some(&mut diff_opts),
+      ).expect("diff between trees should be there");
+
+    // detect renamed and copied files, which would otherwise show up as
+    // separate additions and deletions
+    let mut find_opts = DiffFindOptions::new();
+    find_opts
+      .renames(true)
+      .copies(true)
+      .rename_threshold(config::DIFF_RENAME_THRESHOLD)
+      .copy_threshold(config::DIFF_COPY_THRESHOLD);
+    diff
+      .find_similar(Some(&mut find_opts))
+      .expect("should be able to detect renames");
+
+    let changed_paths: Vec<PathBuf> = diff
+      .deltas()
+      .filter_map(|diff_delta| diff_delta.new_file().path().map(Path::to_path_buf))
+      .collect();
+    let page_up_to_date = page_up_to_date(&changed_paths);
+    self.collect_last_commit_times(commit, changed_paths);
+
+    // ========================================================================
+    #[derive(Debug)]
+    struct DeltaInfo<'delta> {
+      id: usize,
+
+      add_count: usize,
+      del_count: usize,
+      delta:     DiffDelta<'delta>,
+
+      new_path: &'delta Path,
+      old_path: &'delta Path,
+
+      num_hunks: usize,
+      is_binary: bool,
+    }
+
+    if !render_page || self.written_commits.borrow().contains(&commit.id()) {
+      return Ok(());
+    }
+
+    if page_up_to_date {
+      Stats::incr(&self.stats.commits_skipped);
+      return Ok(());
+    }
+    Stats::incr(&self.stats.commits_written);
+    self.written_commits.borrow_mut().insert(commit.id());
+    let start = self.verbose.then(Instant::now);
+
+    let sig = commit.author();
+    let time = sig.when();
+
+    let deltas_iter = diff.deltas();
+    let mut deltas: Vec<DeltaInfo<'_>> = Vec::with_capacity(deltas_iter.len());
+    for (delta_id, diff_delta) in deltas_iter.enumerate() {
+      // filter desired deltas
+      if !matches!(diff_delta.status(),
+                   Delta::Added    | Delta::Copied | Delta::Deleted |
+                   Delta::Modified | Delta::Renamed) {
+        continue;
+      }
+
+      let old_file = diff_delta.old_file();
+      let new_file = diff_delta.new_file();
+      let old_path = &old_file.path().unwrap();
+      let new_path = &new_file.path().unwrap();
+
+      let patch = Patch::from_diff(&diff, delta_id)
+        .unwrap()
+        .expect("diff should have patch");
+
+      let num_hunks = patch.num_hunks();
+      let is_binary = is_binary(
+        new_path,
+        old_file.is_binary() || new_file.is_binary()
+      );
+
+      let mut delta_info = DeltaInfo {
+        id: delta_id,
+        add_count: 0,
+        del_count: 0,
+        delta: diff_delta,
+        old_path,
+        new_path,
+        num_hunks,
+        is_binary,
+      };
+
+      for hunk_id in 0..num_hunks {
+        let lines_of_hunk = patch
+          .num_lines_in_hunk(hunk_id)
+          .unwrap();
+
+        for line_id in 0..lines_of_hunk {
+          let line = patch
+            .line_in_hunk(hunk_id, line_id)
+            .unwrap();
+
+          // NOTE: context lines have both line numbers, but the "no newline
+          // at end of file" markers have neither, so we can't rely on the
+          // line numbers alone
+          match line.origin_value() {
+            DiffLineType::Addition => delta_info.add_count += 1,
+            DiffLineType::Deletion => delta_info.del_count += 1,
+            _                      => {}
+          }
+        }
+      }
+
+      deltas.push(delta_info);
+    }
+
+    // ========================================================================
+    // NOTE: Diff::stats would walk through every line of the diff all over
+    //       again, which used to take upwards of 76% of execution-time: the
+    //       totals are accumulated from the counts of each delta instead
+    let files_changed = deltas.len();
+    let insertions: usize = deltas.iter().map(|d| d.add_count).sum();
+    let deletions:  usize = deltas.iter().map(|d| d.del_count).sum();
+
+    let mut f = create_html_file(&path, self.gzip)?;
+
+    let summary = commit
+      .summary()
+      .expect("commit summary should be valid UTF-8");
+
+    self.render_header(
+      &mut f,
+      PageTitle::Commit { repo_name: self.name, summary }
+    )?;
+
+    writeln!(&mut f, "<article class=\"commit\">")?;
+    writeln!(&mut f, "<dl>")?;
+
+    writeln!(&mut f, "<dt>Commit</dt>")?;
+    writeln!(&mut f, "<dd><a href=\"/{root}{name}/{COMMIT_SUBDIR}/{id}.html\">{id}</a></dd>",
+                     root = self.output_root,
+                     name = EscapedPath(self.name), id = commit.id())?;
+
+    match commit.parent_count() {
+      0 => {}
+      1 => writeln!(&mut f, "<dt>Parent</dt>")?,
+      _ => writeln!(&mut f, "<dt>Parents</dt>")?,
+    }
+    for parent_id in commit.parent_ids() {
+      if self.has_commit_page(parent_id) {
+        writeln!(
+          &mut f,
+          "<dd><a href=\"/{root}{name}/{COMMIT_SUBDIR}/{id}.html\">{id}</a></dd>",
+          root = self.output_root,
+          name = EscapedPath(self.name),
+          id = parent_id,
+        )?;
+      } else {
+        writeln!(&mut f, "<dd>{parent_id}</dd>")?;
+      }
+    }
+
+    writeln!(&mut f, "<dt>Author</dt>")?;
+    write!(&mut f, "<dd>{name}", name = Escaped(sig.name().unwrap()))?;
+    if let Some(email) = sig.email() {
+      write!(&mut f, " &lt;<a href=\"mailto:{url}\">{email}</a>&gt;",
+                     url = EscapedUrl(email), email = Escaped(email))?;
+    }
+    writeln!(&mut f, "</dd>")?;
+
+    writeln!(&mut f, "<dt>Date</dt>")?;
+    writeln!(&mut f, "<dd><time datetime=\"{datetime}\">{date}</time></dd>",
+                     datetime = DateTime(time), date = FullDate(time))?;
+
+    // NOTE: we do not verify the signature, we only show the key it claims
+    // to be signed with
+    if let Ok((signature, _)) = self.repo.extract_signature(&commit.id(), None) {
+      writeln!(&mut f, "<dt>Signature</dt>")?;
+      match Signer::parse(&String::from_utf8_lossy(&signature)) {
+        Signer::Unknown => {
+          writeln!(&mut f, "<dd><span class=\"signed\">Signed</span></dd>")?;
+        }
+        signer => {
+          writeln!(&mut f, "<dd><span class=\"signed\">Signed</span> with <code>{signer}</code></dd>",
+                           signer = Escaped(&signer.to_string()))?;
+        }
+      }
+    }
+
+    writeln!(&mut f, "</dl>")?;
+
+    let message = commit
+      .message()
+      .expect("commit message should be valid UTF-8")
+      .trim();
+    let (subject, body) = message.split_once("\n\n").unwrap_or((message, ""));
+    let (body, trailers) = trailers::split(body);
+
+    let issue_url = config::ISSUE_URL.map(|url| url.replace("{name}", self.name));
+    let autolink = config::AUTOLINK.then_some(markdown::Autolink {
+      issue_url: issue_url.as_deref(),
+    });
+
+    // the subject is always plain text, since it's also displayed in the log
+    writeln!(&mut f, "<p>")?;
+    markdown::write_autolinked(&mut f, subject.trim(), autolink)?;
+    writeln!(&mut f, "\n</p>")?;
+
+    if config::COMMIT_MARKDOWN {
+      let blob_root = self.blob_root();
+      let location = markdown::Location { blob_root: &blob_root, dir: "" };
+      markdown::render_html(&mut f, &body.to_string(), 2, location, autolink)?;
+    } else {
+      for p in body.split("\n\n").filter(|p| !p.trim().is_empty()) {
+        writeln!(&mut f, "<p>")?;
+        markdown::write_autolinked(&mut f, p.trim(), autolink)?;
+        writeln!(&mut f, "\n</p>")?;
+      }
+    }
+
+    if !trailers.is_empty() {
+      writeln!(&mut f, "<dl class=\"trailers\">")?;
+      for trailer in &trailers {
+        writeln!(&mut f, "<dt>{key}</dt>", key = Escaped(trailer.key))?;
+        write!(&mut f, "<dd>")?;
+        if let Some((name, email)) = trailers::split_email(&trailer.value) {
+          write!(&mut f, "{name} &lt;<a href=\"mailto:{url}\">{email}</a>&gt;",
+                         name = Escaped(name),
+                         url = EscapedUrl(email),
+                         email = Escaped(email))?;
+        } else {
+          markdown::write_autolinked(&mut f, &trailer.value, autolink)?;
+        }
+        writeln!(&mut f, "</dd>")?;
+      }
+      writeln!(&mut f, "</dl>")?;
+    }
+
+    writeln!(&mut f, "</article>")?;
+
+    // ========================================================================
+    writeln!(&mut f, "<h2>Diffstats</h2>")?;
+    if commit.parent_count() > 1 {
+      writeln!(&mut f, "<p>This is a merge commit with {n} parents: the changes below are relative to the first parent.</p>",
+                       n = commit.parent_count())?;
+    }
+    writeln!(&mut f, "<p>{c} files changed, {i} insertions, {d} deletions</p>",
+             c = files_changed,
+             i = insertions,
+             d = deletions,)?;
+
+    writeln!(&mut f, "<div class=\"table-container\">")?;
+    writeln!(&mut f, "<table>")?;
+    writeln!(&mut f, "<thead>")?;
+    writeln!(&mut f, "<tr>")?;
+    writeln!(&mut f, "<td>Status</td>")?;
+    writeln!(&mut f, "<td>Name</td>")?;
+    writeln!(&mut f, "<td align=\"right\">Changes</td>")?;
+    writeln!(&mut f, "<td align=\"right\">Insertions</td>")?;
+    writeln!(&mut f, "<td align=\"right\">Deletions</td>")?;
+    writeln!(&mut f, "</tr>")?;
+    writeln!(&mut f, "</thead>")?;
+    writeln!(&mut f, "<tbody>")?;
+
+    for delta_info in &deltas {
+      let delta_id = delta_info.id;
+
+      writeln!(&mut f, "<tr>")?;
+
+      write!(&mut f, "<td style=\"width: 4em;\">")?;
+      match delta_info.delta.status() {
+        Delta::Added    => write!(&mut f, "Added")?,
+        Delta::Copied   => write!(&mut f, "Copied")?,
+        Delta::Deleted  => write!(&mut f, "Deleted")?,
+        Delta::Modified => write!(&mut f, "Modified")?,
+        Delta::Renamed  => write!(&mut f, "Renamed")?,
+        _               => unreachable!("other delta types should have been filtered out"),
+      }
+      writeln!(&mut f, "</td>")?;
+
+      let old_file = delta_info.delta.old_file();
+      let new_file = delta_info.delta.new_file();
+      let old_path = old_file.path().unwrap().to_string_lossy();
+      let new_path = new_file.path().unwrap().to_string_lossy();
+
+      if old_path == new_path {
+        writeln!(&mut f, "<td><a href=\"#d{delta_id}\">{old_path}</a></td>",
+                         old_path = Escaped(&old_path))?
+      } else {
+        writeln!(&mut f, "<td><a href=\"#d{delta_id}\">{old_path} &rarr; {new_path}</a></td>",
+                         old_path = Escaped(&old_path),
+                         new_path = Escaped(&new_path))?
+      }
+
+      match delta_info.delta.nfiles() {
+        1 => writeln!(&mut f, "<td align=\"right\">1 file changed</td>")?,
+        n => writeln!(&mut f, "<td align=\"right\">{n} files changed</td>")?,
+      }
+      writeln!(&mut f, "<td align=\"right\" style=\"width: 4em;\">{i}</td>",
+                       i = delta_info.add_count)?;
+      writeln!(&mut f, "<td align=\"right\" style=\"width: 4em;\">{d}</td>",
+                       d = delta_info.del_count)?;
+      writeln!(&mut f, "</tr>")?;
+    }
+
+    writeln!(&mut f, "</tbody>")?;
+    writeln!(&mut f, "</table>")?;
+    writeln!(&mut f, "</div>")?;
+
+    // ========================================================================
+    for delta_info in deltas {
+      let delta_id = delta_info.id;
+
+      if config::COLLAPSIBLE_DIFFS {
+        // the diffs are open by default, so that the links to the hunks and
+        // lines of the diff still work
+        writeln!(&mut f, "<details open id=\"d{delta_id}\">")?;
+        write!(&mut f, "<summary>")?;
+        if delta_info.old_path == delta_info.new_path {
+          write!(&mut f, "{path}", path = Escaped(&delta_info.new_path.to_string_lossy()))?;
+        } else {
+          write!(&mut f, "{old_path} &rarr; {new_path}",
+                         old_path = Escaped(&delta_info.old_path.to_string_lossy()),
+                         new_path = Escaped(&delta_info.new_path.to_string_lossy()))?;
+        }
+        writeln!(&mut f, " <span class=\"i\">+{i}</span> <span class=\"d\">-{d}</span></summary>",
+                         i = delta_info.add_count,
+                         d = delta_info.del_count)?;
+        writeln!(&mut f, "<div class=\"code-block\">")?;
+      } else {
+        writeln!(&mut f, "<div class=\"code-block\" id=\"d{delta_id}\">")?;
+      }
+
+      // the files are linked to their pages in the tree of the rendered
+      // branch, which may not have them anymore
+      let new_page = self.blob_page_url(delta_info.new_path);
+
+      match (delta_info.delta.status(), &new_page) {
+        (Delta::Added, Some(new_page)) => {
+          writeln!(
+            &mut f,
+            "<pre{TabSize}><b>diff --git /dev/null b/<a href=\"{new_page}\">{new_path}</a></b>",
+            new_path = Escaped(&delta_info.new_path.to_string_lossy()),
+          )?;
+        }
+        (Delta::Added, None) => {
+          writeln!(
+            &mut f,
+            "<pre{TabSize}><b>diff --git /dev/null b/{new_path}</b>",
+            new_path = Escaped(&delta_info.new_path.to_string_lossy()),
+          )?;
+        }
+        (Delta::Deleted, _) => {
+          writeln!(
+            &mut f,
+            "<pre{TabSize}><b>diff --git a/{old_path} /dev/null</b>",
+            old_path = Escaped(&delta_info.old_path.to_string_lossy()),
+          )?;
+        }
+        (_, Some(new_page)) => {
+          writeln!(
+            &mut f,
+            "<pre{TabSize}><b>diff --git a/<a id=\"d#{delta_id}\" href=\"{new_page}\">{old_path}</a> b/<a href=\"{new_page}\">{new_path}</a></b>",
+            new_path = Escaped(&delta_info.new_path.to_string_lossy()),
+            old_path = Escaped(&delta_info.old_path.to_string_lossy()),
+          )?;
+        }
+        (_, None) => {
+          writeln!(
+            &mut f,
+            "<pre{TabSize}><b>diff --git a/{old_path} b/{new_path}</b>",
+            new_path = Escaped(&delta_info.new_path.to_string_lossy()),
+            old_path = Escaped(&delta_info.old_path.to_string_lossy()),
+          )?;
+        }
+      }
+
+      if delta_info.is_binary {
+        writeln!(&mut f, "Binary files differ")?;
+      } else {
+        let patch = Patch::from_diff(&diff, delta_info.id)
+          .unwrap()
+          .expect("diff should have patch");
+
+        for hunk_id in 0..delta_info.num_hunks {
+          // we cannot cache the hunks:
+          // libgit invalidates the data after a while
+          let (hunk, lines_of_hunk) = patch.hunk(hunk_id).unwrap();
+
+          write!(&mut f, "<a href=\"#d{delta_id}-{hunk_id}\" id=\"d{delta_id}-{hunk_id}\" class=\"h\">")?;
+          write!(&mut f, "{}", Escaped(&String::from_utf8_lossy(hunk.header())))?;
+          write!(&mut f, "</a>")?;
+
+          for line_id in 0..lines_of_hunk {
+            let line = patch.line_in_hunk(hunk_id, line_id).unwrap();
+            let line_content = String::from_utf8_lossy(line.content());
+            let line_content = expand_tabs(&line_content);
+
+            // added and context lines link to the same line in the page of the
+            // new version of the file
+            let new_lineno = match line.origin_value() {
+              DiffLineType::Addition | DiffLineType::Context => line.new_lineno(),
+              _                                              => None,
+            };
+
+            // the anchors of added and deleted lines are identified by the
+            // line number of the line in the new and old files, respectively
+            let new_line = new_lineno.zip(new_page.as_deref());
+            match (diff_line_anchor(&line), new_line) {
+              (Some((origin, class, lineno)), Some((new_lineno, new_page))) => {
+                write!(
+                  &mut f,
+                  "<a href=\"{new_page}#l{new_lineno}\" id=\"d{delta_id}-{hunk_id}-{class}{lineno}\" class=\"{class}\">{origin}{line}</a>",
+                  line = Escaped(&line_content),
+                )?;
+              }
+              (Some((origin, class, lineno)), None) => {
+                write!(
+                  &mut f,
+                  "<a href=\"#d{delta_id}-{hunk_id}-{class}{lineno}\" id=\"d{delta_id}-{hunk_id}-{class}{lineno}\" class=\"{class}\">{origin}{line}</a>",
+                  line = Escaped(&line_content),
+                )?;
+              }
+              (None, Some((new_lineno, new_page))) => {
+                write!(
+                  &mut f,
+                  "<a href=\"{new_page}#l{new_lineno}\" class=\"c\"> {line}</a>",
+                  line = Escaped(&line_content),
+                )?;
+              }
+              (None, None) => {
+                write!(&mut f, " {line}", line = Escaped(&line_content))?;
+              }
+            }
+          }
+        }
+      }
+
+      writeln!(&mut f, "</pre>")?;
+      writeln!(&mut f, "</div>")?;
+
+      if config::COLLAPSIBLE_DIFFS {
+        writeln!(&mut f, "</details>")?;
+      }
+    }
+
+    // ========================================================================
+    writeln!(&mut f, "</main>")?;
+    render_footer(&mut f)?;
+    writeln!(&mut f, "</body>")?;
+    writeln!(&mut f, "</html>")?;
+    f.finish()?;
+
+    if let Some(start) = start {
+      infoln!("Rendered {path:?} in {:?}", start.elapsed());
+    }
+
+    Ok(())
+  }
+
+  fn render_summary(&self) -> io::Result<()> {
+    let mut path = self.output_path.clone();
+    path.push(self.name);
+
+    if !path.is_dir() { create_dir(&path)?; }
+    path.push("index.html");
+
+    let mut f = create_html_file(path, self.gzip)?;
+
+    // ========================================================================
+    self.render_header(&mut f, PageTitle::Summary { repo_name: self.name })?;
+
+    writeln!(&mut f, "<ul>")?;
+    writeln!(&mut f, "<li>refs: {branch}</li>",
+                     branch = Escaped(&self.branch))?;
+    // scp-like URLs aren't valid links
+    if repo_config::LINKED_SCHEMES.iter().any(|s| self.clone_url.starts_with(s)) {
+      writeln!(
+        &mut f,
+        "<li>git clone: <a href=\"{url}\">{clone_url}</a></li>",
+        url = EscapedUrl(self.clone_url),
+        clone_url = Escaped(self.clone_url),
+      )?;
+    } else {
+      writeln!(&mut f, "<li>git clone: <code>{clone_url}</code></li>",
+                       clone_url = Escaped(self.clone_url))?;
+    }
+
+    // the branch checked out by git clone is the one HEAD points to, which
+    // isn't the rendered branch if running with --branch
+    let default_branch = self
+      .repo
+      .head()
+      .ok()
+      .and_then(|head| head.shorthand().map(String::from))
+      .unwrap_or_else(|| self.branch.clone());
+    writeln!(&mut f, "<li>default branch: <code>{branch}</code></li>",
+                     branch = Escaped(&default_branch))?;
+    if default_branch != self.branch {
+      writeln!(
+        &mut f,
+        "<li>clone {branch}: <code>git clone -b {branch} {clone_url}</code></li>",
+        branch = Escaped(&self.branch),
+        clone_url = Escaped(self.clone_url),
+      )?;
+    }
+    writeln!(&mut f, "</ul>")?;
+
+    if let Some(readme) = &self.readme {
+      let blob_root = self.blob_root();
+      readme.render(&mut f, markdown::Location { blob_root: &blob_root, dir: "" })?;
+    }
+
+    writeln!(&mut f, "</main>")?;
+    render_footer(&mut f)?;
+    writeln!(&mut f, "</body>")?;
+    writeln!(&mut f, "</html>")?;
+    f.finish()?;
+
+    Ok(())
+  }
+
+  /// Renders machine-readable metadata about the repository to `info.json`
+  fn render_json(&self) -> io::Result<()> {
+    let mut path = self.output_path.clone();
+    path.push(self.name);
+    path.push("info.json");
+
+    let mut f = create_output(path)?;
+
+    // ========================================================================
+    writeln!(&mut f, "{{")?;
+    writeln!(&mut f, "  \"name\": \"{}\",", JsonEscaped(self.name))?;
+    writeln!(&mut f, "  \"owner\": \"{}\",", JsonEscaped(self.owner.trim()))?;
+    if let Some(description) = self.description {
+      writeln!(&mut f, "  \"description\": \"{}\",",
+                       JsonEscaped(description.trim()))?;
+    } else {
+      writeln!(&mut f, "  \"description\": null,")?;
+    }
+    writeln!(&mut f, "  \"branch\": \"{}\",", JsonEscaped(&self.branch))?;
+    writeln!(&mut f, "  \"last_commit\": {},", self.last_commit.seconds())?;
+    writeln!(&mut f, "  \"last_commit_offset\": {},",
+                     self.last_commit.offset_minutes())?;
+    writeln!(&mut f, "  \"first_commit\": {}", self.first_commit)?;
+    writeln!(&mut f, "}}")?;
+
+    f.flush()
+  }
+
+  /// Renders one of the `SPECIAL_DOCS` to its own page
+  fn render_doc(&self, doc: &Doc) -> io::Result<()> {
+    let mut path = self.output_path.clone();
+    path.push(self.name);
+    path.push(doc.kind.page);
+
+    let mut f = create_html_file(path, self.gzip)?;
+
+    // ========================================================================
+    self.render_header(
+      &mut f,
+      PageTitle::Doc { repo_name: self.name, label: doc.kind.label },
+    )?;
+    writeln!(&mut f, "<section id=\"{label}\">", label = doc.kind.label)?;
+    if doc.kind.format == ReadmeFormat::Md {
+      let blob_root = self.blob_root();
+      let location = markdown::Location { blob_root: &blob_root, dir: "" };
+      markdown::render_html(&mut f, &doc.content, 0, location, None)?;
+    } else {
+      writeln!(&mut f, "<pre>{}</pre>", Escaped(&doc.content))?;
+    }
+    writeln!(&mut f, "</section>")?;
+
+    writeln!(&mut f, "</main>")?;
+    render_footer(&mut f)?;
+    writeln!(&mut f, "</body>")?;
+    writeln!(&mut f, "</html>")?;
+    f.finish()?;
+
+    Ok(())
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Blob {
+  id:   Oid,
+  mode: Mode,
+}
+
+#[derive(Clone, Copy, Debug)]
+/// POSIX filemode
+struct Mode(pub i32);
+
+impl Mode {
+  const S_IFMT:  i32 = 0o170000; // file type mask
+  const S_IFLNK: i32 = 0o120000; // symbolic link
+
+  fn is_symlink(self) -> bool {
+    self.0 & Self::S_IFMT == Self::S_IFLNK
+  }
+}
+
+impl Display for Mode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    const S_IFMT:   i32 = Mode::S_IFMT;
+    const S_IFREG:  i32 = 0o100000; // regular file
+    const S_IFDIR:  i32 = 0o040000; // directory
+    const S_IFCHR:  i32 = 0o020000; // character device
+    const S_IFBLK:  i32 = 0o060000; // block device
+    const S_IFIFO:  i32 = 0o010000; // FIFO (named pipe)
+    const S_IFLNK:  i32 = Mode::S_IFLNK;
+    const S_IFSOCK: i32 = 0o140000; // socket
+    const S_ISUID:  i32 = 0o4000;   // set-user-ID bit
+    const S_ISGID:  i32 = 0o2000;   // set-group-ID bit
+    const S_ISVTX:  i32 = 0o1000;   // sticky bit
+    const S_IRUSR:  i32 = 0o4<<6;   // read permission for the owner
+    const S_IWUSR:  i32 = 0o2<<6;   // write permission for the owner
+    const S_IXUSR:  i32 = 0o1<<6;   // execute permission for the owner
+    const S_IRGRP:  i32 = 0o4<<3;   // read permission for the group
+    const S_IWGRP:  i32 = 0o2<<3;   // write permission for the group
+    const S_IXGRP:  i32 = 0o1<<3;   // execute permission for the group
+    const S_IROTH:  i32 = 0o4;      // read permission for others
+    const S_IWOTH:  i32 = 0o2;      // write permission for others
+    const S_IXOTH:  i32 = 0o1;      // execute permission for others
+
+    let m = self.0;
+
+    match m & S_IFMT { // filetype
+      S_IFREG  => write!(f, "-")?,
+      S_IFDIR  => write!(f, "d")?,
+      S_IFCHR  => write!(f, "c")?,
+      S_IFBLK  => write!(f, "b")?,
+      S_IFIFO  => write!(f, "p")?,
+      S_IFLNK  => write!(f, "l")?,
+      S_IFSOCK => write!(f, "s")?,
+      _        => write!(f, "?")?, // unknown type
+    }
+
+    if m & S_IRUSR != 0 { // owner read
+      write!(f, "r")?;
+    } else {
+      write!(f, "-")?;
+    }
+
+    if m & S_IWUSR != 0 { // owner write
+      write!(f, "w")?;
+    } else {
+      write!(f, "-")?;
+    }
+
+    match (m & S_ISUID != 0, m & S_IXUSR != 0) { // owner execute
+      (true, true)   => write!(f, "s")?,
+      (true, false)  => write!(f, "S")?,
+      (false, true)  => write!(f, "x")?,
+      (false, false) => write!(f, "-")?,
+    }
+
+    if m & S_IRGRP != 0 { // group read
+      write!(f, "r")?;
+    } else {
+      write!(f, "-")?;
+    }
+
+    if m & S_IWGRP != 0 { // group write
+      write!(f, "w")?;
+    } else {
+      write!(f, "-")?;
+    }
+
+    match (m & S_ISGID != 0, m & S_IXGRP != 0) { // group execute
+      (true, true)   => write!(f, "s")?,
+      (true, false)  => write!(f, "S")?,
+      (false, true)  => write!(f, "x")?,
+      (false, false) => write!(f, "-")?,
+    }
+
+    if m & S_IROTH != 0 { // others read
+      write!(f, "r")?;
+    } else {
+      write!(f, "-")?;
+    }
+
+    if m & S_IWOTH != 0 { // others write
+      write!(f, "w")?;
+    } else {
+      write!(f, "-")?;
+    }
+
+    match (m & S_ISVTX != 0, m & S_IXOTH != 0) { // others execute
+      (true, true)   => write!(f, "t")?,
+      (true, false)  => write!(f, "T")?,
+      (false, true)  => write!(f, "x")?,
+      (false, false) => write!(f, "-")?,
+    }
+
+    Ok(())
+  }
+}
+
+/// The `download` attribute of a link to a raw blob, if any, suggesting the
+/// file name of the blob at the given path
+#[derive(Clone, Copy, Debug)]
+struct DownloadAttr<'a>(Option<&'a Path>);
+
+impl Display for DownloadAttr<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(name) = self.0.and_then(Path::file_name) {
+      write!(f, " download=\"{}\"", Escaped(&name.to_string_lossy()))?;
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FileSize(usize);
+
+impl Display for FileSize {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (k, units) = match config::SIZE_UNITS {
+      SizeUnits::Si  => (1000, ["kB", "MB", "GB"]),
+      SizeUnits::Iec => (1024, ["KiB", "MiB", "GiB"]),
+    };
+    let m = k * k;
+    let g = m * k;
+
+    let size = self.0;
+
+    if size >= g {
+      write!(f, "{:.1} {}", size as f64 / g as f64, units[2])
+    } else if size >= m {
+      write!(f, "{:.1} {}", size as f64 / m as f64, units[1])
+    } else if size >= k {
+      write!(f, "{:.1} {}", size as f64 / k as f64, units[0])
+    } else {
+      write!(f, "{} bytes", size)
+    }
+  }
+}
+
+/// The path of the page of the blob at `path`, relative to `TREE_SUBDIR`
+///
+/// The page of a blob named `index` would collide with the `index.html` page of
+/// its parent directory, and the one of a blob named `index.html` could be
+/// mistaken for it: `.blob` is appended to the names of such blobs. So it is to
+/// the names of blobs which look like escaped ones, such as `index.blob`, so
+/// that no two blobs share a page.
+fn blob_page_path(path: &Path) -> String {
+  let path = path.to_string_lossy();
+  let name = path.rsplit('/').next().unwrap_or(&path);
+
+  if is_reserved_page_name(name) {
+    format!("{path}.blob.html")
+  } else {
+    format!("{path}.html")
+  }
+}
+
+/// The inverse of [`blob_page_path`]: returns the name of the blob whose page
+/// is `{page}.html`, if any
+fn blob_name_of_page(page: &str) -> Option<&str> {
+  if is_reserved_page_name(page) {
+    page.strip_suffix(".blob")
+  } else {
+    Some(page)
+  }
+}
+
+/// Checks if `name` is `index` or `index.html`, possibly followed by any
+/// number of `.blob` suffixes
+fn is_reserved_page_name(name: &str) -> bool {
+  let mut stem = name;
+  while let Some(s) = stem.strip_suffix(".blob") {
+    stem = s;
+  }
+
+  stem == "index" || stem == "index.html"
+}
+
+/// Sets the width of tab stops of a code block to `config::TAB_WIDTH`, if
+/// `config::TAB_MODE` is `TabMode::Css`
+struct TabSize;
+
+impl Display for TabSize {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if config::TAB_MODE == TabMode::Css {
+      write!(f, " style=\"tab-size: {};\"", config::TAB_WIDTH)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Replaces the tabs in `line` by spaces up to the next tab stop, if
+/// `config::TAB_MODE` is `TabMode::Expand`
+fn expand_tabs(line: &str) -> Cow<'_, str> {
+  if config::TAB_MODE != TabMode::Expand || !line.contains('\t') {
+    return Cow::Borrowed(line);
+  }
+
+  Cow::Owned(expand_tabs_to(line, config::TAB_WIDTH))
+}
+
+/// Replaces the tabs in `line` by spaces up to the next multiple of `width`,
+/// counting columns in characters
+fn expand_tabs_to(line: &str, width: usize) -> String {
+  let mut result = String::with_capacity(line.len() + width);
+  let mut column = 0;
+  for c in line.chars() {
+    if c == '\t' {
+      let spaces = width - column % width;
+      result.extend(std::iter::repeat_n(' ', spaces));
+      column += spaces;
+    } else {
+      result.push(c);
+      column += 1;
+    }
+  }
+
+  result
+}
+
+/// The number of lines of a text file
+///
+/// A trailing newline terminates the last line rather than starting a new one.
+fn line_count<R: BufRead>(content: R) -> io::Result<usize> {
+  content.split(b'\n').try_fold(0, |n, line| line.map(|_| n + 1))
+}
+
+/// A blob page whose header was already rendered
+struct BlobPage {
+  f:    OutputFile,
+  blob: Blob,
+  path: PathBuf,
+  /// Whether the deduplicated copy of the blob should be written along with
+  /// this page, if `output.dedup_blobs` is set
+  write_object: bool,
+}
+
+/// The information about the rendered branch needed to render blob pages,
+/// which can be shared between threads
+struct BlobContext {
+  blob_root:      String,
+  raw_blobs_path: PathBuf,
+  /// Where the deduplicated copies of the blobs are written
+  objects_path:   PathBuf,
+  /// The path of `objects_path` relative to `raw_blobs_path`
+  objects_link:   String,
+  verbose:        bool,
+}
+
+/// A raw file to be linked to the deduplicated copy of a blob
+struct BlobLink {
+  id:   Oid,
+  path: PathBuf,
+}
+
+impl BlobContext {
+  /// Links raw files to the deduplicated copies of their blobs, unless they
+  /// are already linked to them
+  fn link_blobs(&self, links: Vec<BlobLink>) -> io::Result<()> {
+    for BlobLink { id, path } in links {
+      let object_path = self.objects_path.join(id.to_string());
+
+      // symlinks are relative to the directory of the raw file
+      let depth = path
+        .strip_prefix(&self.raw_blobs_path)
+        .map_or(0, |p| p.components().count() - 1);
+      let target = format!("{}{}/{id}", "../".repeat(depth), self.objects_link);
+
+      let linked = match config::DEDUP_BLOBS {
+        DedupBlobs::Off      => unreachable!("blobs aren't linked unless deduplicated"),
+        DedupBlobs::Symlink  => {
+          fs::read_link(&path).is_ok_and(|t| t == Path::new(&target))
+        }
+        DedupBlobs::Hardlink => {
+          match (fs::symlink_metadata(&path), fs::metadata(&object_path)) {
+            (Ok(a), Ok(b)) => a.is_file() && a.dev() == b.dev() && a.ino() == b.ino(),
+            _              => false,
+          }
+        }
+      };
+      if linked {
+        record_file_hash(&path)?;
+        continue;
+      }
+
+      if is_dry_run() {
+        infoln!("Would link {path:?} to {object_path:?}");
+        continue;
+      }
+
+      // there may be a copy of the blob from before the blobs were
+      // deduplicated, or a link to an outdated blob
+      if fs::symlink_metadata(&path).is_ok() {
+        if let Err(e) = fs::remove_file(&path) {
+          errorln!("Failed to remove {path:?}: {e}");
+          return Err(with_path(e, "remove", &path));
+        }
+      }
+
+      let result = match config::DEDUP_BLOBS {
+        DedupBlobs::Symlink => unix::fs::symlink(&target, &path),
+        _                   => fs::hard_link(&object_path, &path),
+      };
+      if let Err(e) = result {
+        errorln!("Failed to link {path:?} to {object_path:?}: {e}");
+        return Err(with_path(e, "link", &path));
+      }
+      record_file_hash(&path)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Renders the rest of the page of a blob and writes the raw blob
+///
+/// Returns the raw file which should be linked to the deduplicated copy of
+/// the blob, if `output.dedup_blobs` is set.
+fn render_blob_page(
+  repo: &Repository,
+  page: BlobPage,
+  ctx: &BlobContext,
+) -> io::Result<Option<BlobLink>> {
+  let BlobPage { mut f, blob, path, write_object } = page;
+  let start = ctx.verbose.then(Instant::now);
+
+  let id = blob.id;
+  let mode = blob.mode;
+  let odb = repo.odb().expect("should be able to open the object database");
+  let blob = BlobContent::open(repo, &odb, blob.id);
+  let is_binary = is_binary(&path, blob.is_binary()?);
+  let too_large = config::MAX_BLOB_SIZE.is_some_and(|max| blob.size() > max);
+  // browsers would just display garbage for most binary files: suggest
+  // downloading them instead
+  let download = is_binary && !is_viewable_inline(&path);
+
+  let mut link = None;
+  if !too_large {
+    let mut raw_blob_path = ctx.raw_blobs_path.clone();
+    raw_blob_path.extend(&path);
+
+    if config::DEDUP_BLOBS == DedupBlobs::Off {
+      remove_blob_link(&raw_blob_path)?;
+      copy_if_changed(&raw_blob_path, blob.size(), || blob.reader())?;
+    } else {
+      if write_object {
+        let object_path = ctx.objects_path.join(id.to_string());
+        copy_if_changed(&object_path, blob.size(), || blob.reader())?;
+      }
+      link = Some(BlobLink { id, path: raw_blob_path });
+    }
+  }
+
+  // ==========================================================================
+  writeln!(&mut f, "<div class=\"table-container\">")?;
+  writeln!(&mut f, "<table>")?;
+  writeln!(&mut f, "<colgroup>")?;
+  writeln!(&mut f, "<col />")?;
+  writeln!(&mut f, "<col />")?;
+  writeln!(&mut f, "<col style=\"width: 7em;\"/>")?;
+  writeln!(&mut f, "</colgroup>")?;
+  writeln!(&mut f, "<thead>")?;
+  writeln!(&mut f, "<tr><td>Name</td><td align=\"right\">Size</td><td align=\"right\">Mode</td></tr>")?;
+  writeln!(&mut f, "</thead>")?;
+  writeln!(&mut f, "<tbody>")?;
+  writeln!(&mut f, "<tr>")?;
+  writeln!(&mut f, "<td><a href=\"./\" class=\"subtree\">..</a></td>")?;
+  writeln!(&mut f, "<td align=\"right\"></td>")?;
+  writeln!(&mut f, "<td align=\"right\"></td>")?;
+  writeln!(&mut f, "</tr>")?;
+  writeln!(&mut f, "<tr>")?;
+  if too_large {
+    writeln!(&mut f, "<td>{path}</td>", path = Escaped(&path.to_string_lossy()))?;
+  } else {
+    writeln!(&mut f, "<td><a href=\"{blob_root}/{url}\"{download}>{path}</a></td>",
+                     blob_root = ctx.blob_root,
+                     url = EscapedPath(&path.to_string_lossy()),
+                     path = Escaped(&path.to_string_lossy()),
+                     download = DownloadAttr(download.then_some(&path)))?;
+  }
+  let has_content = !is_binary && !too_large && blob.size() > 0;
+  let lines = if has_content { line_count(blob.reader())? } else { 0 };
+
+  if config::TEXT_LOC && !is_binary && !too_large {
+    match lines {
+      1 => writeln!(&mut f, "<td align=\"right\">1 line</td>")?,
+      n => writeln!(&mut f, "<td align=\"right\">{n} lines</td>")?,
+    }
+  } else {
+    writeln!(&mut f, "<td align=\"right\">{}</td>", FileSize(blob.size()))?;
+  }
+  writeln!(&mut f, "<td align=\"right\">{}</td>", mode)?;
+  writeln!(&mut f, "</tr>")?;
+  writeln!(&mut f, "</tbody>")?;
+  writeln!(&mut f, "</table>")?;
+  writeln!(&mut f, "</div>")?;
+
+  if too_large {
+    writeln!(&mut f, "<p>This file is too large to be displayed.</p>")?;
+  } else if download {
+    writeln!(&mut f, "<p>This file cannot be displayed. <a href=\"{blob_root}/{path}\"{download}>Download it</a> instead.</p>",
+                     blob_root = ctx.blob_root,
+                     path = EscapedPath(&path.to_string_lossy()),
+                     download = DownloadAttr(Some(&path)))?;
+  }
+
+  if has_content {
+    render_lines(&mut f, blob.reader(), lines)?;
+
+    // ranges of lines are selected by shift-clicking the line numbers, and
+    // are linked to as #lM-lN
+    writeln!(&mut f, "<script>")?;
+    writeln!(&mut f, "(function() {{")?;
+    writeln!(&mut f, "  var anchor = null;")?;
+    writeln!(&mut f, "  function highlight() {{")?;
+    writeln!(&mut f, "    document.querySelectorAll(\"#blob .hl\").forEach(function(l) {{ l.classList.remove(\"hl\"); }});")?;
+    writeln!(&mut f, "    var m = location.hash.match(/^#l(\\d+)(?:-l(\\d+))?$/);")?;
+    writeln!(&mut f, "    if (!m) return;")?;
+    writeln!(&mut f, "    var start = +m[1], end = m[2] ? +m[2] : start;")?;
+    writeln!(&mut f, "    if (end < start) {{ var t = start; start = end; end = t; }}")?;
+    writeln!(&mut f, "    for (var n = start; n <= end; n++) {{")?;
+    writeln!(&mut f, "      var line = document.getElementById(\"l\" + n);")?;
+    writeln!(&mut f, "      if (line) line.classList.add(\"hl\");")?;
+    writeln!(&mut f, "    }}")?;
+    writeln!(&mut f, "    anchor = start;")?;
+    writeln!(&mut f, "    var first = document.getElementById(\"l\" + start);")?;
+    writeln!(&mut f, "    if (first) first.scrollIntoView();")?;
+    writeln!(&mut f, "  }}")?;
+    writeln!(&mut f, "  document.querySelectorAll(\"#line-numbers a\").forEach(function(a) {{")?;
+    writeln!(&mut f, "    a.addEventListener(\"click\", function(e) {{")?;
+    writeln!(&mut f, "      if (!e.shiftKey || anchor === null) return;")?;
+    writeln!(&mut f, "      e.preventDefault();")?;
+    writeln!(&mut f, "      location.hash = \"#l\" + anchor + \"-\" + a.getAttribute(\"href\").slice(1);")?;
+    writeln!(&mut f, "    }});")?;
+    writeln!(&mut f, "  }});")?;
+    writeln!(&mut f, "  window.addEventListener(\"hashchange\", highlight);")?;
+    writeln!(&mut f, "  highlight();")?;
+    writeln!(&mut f, "}})();")?;
+    writeln!(&mut f, "</script>")?;
+  }
+
+  writeln!(&mut f, "</main>")?;
+  render_footer(&mut f)?;
+  writeln!(&mut f, "</body>")?;
+  writeln!(&mut f, "</html>")?;
+  let page_path = f.path.clone();
+  f.finish()?;
+
+  if let Some(start) = start {
+    infoln!("Rendered {page_path:?} in {:?}", start.elapsed());
+  }
+
+  Ok(link)
+}
+
+/// Prints the contents of a text file with `lines` lines, alongside a gutter
+/// with the line numbers
+fn render_lines<W: Write, R: BufRead>(
+  f: &mut W,
+  content: R,
+  lines: usize,
+) -> io::Result<()> {
+  let log_lines = log_floor(lines);
+
+  writeln!(f, "<div class=\"code-block blob\">")?;
+  writeln!(f, "<pre id=\"line-numbers\">")?;
+
+  for n in 1..=lines {
+    writeln!(f, "<a href=\"#l{n}\">{n:0log_lines$}</a>")?;
+  }
+
+  writeln!(f, "</pre>")?;
+  writeln!(f, "<pre id=\"blob\"{TabSize}>")?;
+
+  // lines are read one at a time, so that large files are never loaded in
+  // memory all at once
+  for (i, line) in content.split(b'\n').enumerate() {
+    let line = line?;
+    let line = line.strip_suffix(b"\r").unwrap_or(&line);
+
+    // text files aren't garanteed to be valid UTF-8: invalid sequences are
+    // replaced by U+FFFD
+    writeln!(f, "<span id=\"l{n}\">{line}</span>",
+      line = Escaped(&expand_tabs(&String::from_utf8_lossy(line))), n = i + 1)?;
+  }
+
+  writeln!(f, "</pre>")?;
+  writeln!(f, "</div>")
+}
+
+fn log_floor(n: usize) -> usize {
+  if n == 0 {
+    return 1;
+  }
+
+  let mut d = 0;
+  let mut m = n;
+
+  while m > 0 {
+    d += 1;
+    m /= 10;
+  }
+
+  d
+}
+
+/// Prints the HTML preamble, including the canonical URL and Open Graph
+/// metadata of the page
+///
+/// `description` is used as the description of the page in link previews.
+fn render_header(
+  f: &mut OutputFile,
+  paths: &Paths,
+  title: PageTitle<'_>,
+  description: Option<&str>,
+) -> io::Result<()> {
+  writeln!(f, "<!DOCTYPE html>")?;
+  write!(f, "<html")?;
+  if let Some(lang) = config::LANG {
+    write!(f, " lang=\"{}\"", Escaped(lang))?;
+  }
+  if let Some(dir) = config::TEXT_DIR {
+    write!(f, " dir=\"{dir}\"")?;
+  }
+  writeln!(f, ">")?;
+  writeln!(f, "<head>")?;
+  writeln!(f, "<meta http-equiv=\"Content-Type\" content=\"text/html; charset=UTF-8\"/>")?;
+  writeln!(f, "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"/>")?;
+  writeln!(f, "<title>{title}</title>")?;
+
+  writeln!(f, "<meta property=\"og:type\" content=\"website\"/>")?;
+  writeln!(f, "<meta property=\"og:title\" content=\"{title}\"/>")?;
+  if let Some(description) = description {
+    writeln!(f, "<meta property=\"og:description\" content=\"{}\"/>",
+                Escaped(description.trim()))?;
+  }
+
+  // absolute URLs can only be constructed if we know where the site is hosted
+  let page_path = f
+    .path
+    .strip_prefix(&paths.output)
+    .ok()
+    .map(|path| path.to_string_lossy().into_owned());
+  if let (Some(base_url), Some(page_path)) = (config::BASE_URL, page_path) {
+    let url = format!("{}/{page_path}", base_url.trim_end_matches('/'));
+    writeln!(f, "<meta property=\"og:url\" content=\"{}\"/>", EscapedUrl(&url))?;
+    writeln!(f, "<link rel=\"canonical\" href=\"{}\"/>", EscapedUrl(&url))?;
+
+    // pages under the private output root are searched with the private
+    // index of projects
+    let output_root = if f.path.starts_with(paths.output_dir(true)) {
+      config::PRIVATE_OUTPUT_ROOT
+    } else {
+      ""
+    };
+    writeln!(f, "<link rel=\"search\" type=\"application/opensearchdescription+xml\" title=\"{}\" href=\"/{}opensearch.xml\"/>",
+                PageTitle::Index, Escaped(output_root))?;
+  }
+
+  writeln!(f, "<link rel=\"icon\" type=\"image/svg\" href=\"{}\" />",
+              EscapedUrl(config::FAVICON))?;
+  writeln!(f, "<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\" />",
+              EscapedUrl(config::STYLESHEET))?;
+  writeln!(f, "</head>")?;
+  writeln!(f, "<body>")?;
+  writeln!(f, "<header>")?;
+  writeln!(f, "<nav>")?;
+  writeln!(f, "<img aria-hidden=\"true\" alt=\"Website logo\" src=\"{}\">",
+              EscapedUrl(config::FAVICON))?;
+  writeln!(f, "<ul>")?;
+  writeln!(f, "<li><strong><a href=\"https://pablopie.xyz\">pablo</a></strong></li>")?;
+  writeln!(f, "<li><a href=\"/\">projects</a></li>")?;
+  writeln!(f, "</ul>")?;
+  writeln!(f, "</nav>")?;
+  writeln!(f, "</header>")?;
+
+  Ok(())
+}
+
+fn render_footer(f: &mut OutputFile) -> io::Result<()> {
+  writeln!(f, "<footer>")?;
+  writeln!(f, "made with ❤️ by <a rel=\"author\" href=\"https://pablopie.xyz/\">@pablo</a>")?;
+  writeln!(f, "</footer>")
+}
+
+/// Renders the page served by the web server for URLs which don't correspond
+/// to any page, at the root of the output
+pub fn render_404(paths: &Paths, flags: Flags) -> io::Result<()> {
+  let mut path = paths.output.clone();
+  path.push("404.html");
+
+  let mut f = create_html_file(path, flags.gzip())?;
+
+  // ==========================================================================
+  render_header(&mut f, paths, PageTitle::NotFound, None)?;
+  writeln!(&mut f, "<main>")?;
+  writeln!(&mut f, "<h1>Page not found</h1>")?;
+  writeln!(&mut f, "<p>")?;
+  writeln!(&mut f, "The page you are looking for does not exist: it may have been moved or deleted.")?;
+  writeln!(&mut f, "Go back to the <a href=\"/\">index of projects</a>.")?;
+  writeln!(&mut f, "</p>")?;
+  writeln!(&mut f, "</main>")?;
+  render_footer(&mut f)?;
+  writeln!(&mut f, "</body>")?;
+  writeln!(&mut f, "</html>")?;
+  f.finish()?;
+
+  Ok(())
+}
+
+/// Renders the index of projects, listing `repos`
+pub fn render_index(
+  paths: &Paths,
+  repos: &[RepoInfo],
+  flags: Flags,
+) -> io::Result<()> {
+  let private = flags.private();
+
+  let mut path = paths.output_dir(private);
+  path.push("index.html");
+
+  // the repositories in the public store which are marked as private are
+  // only listed in the private index
+  let listed = repos.iter().filter(|repo| repo.private == private);
+
+  let output_root = if private {
+    config::PRIVATE_OUTPUT_ROOT
+  } else {
+    ""
+  };
+
+  let mut f = create_html_file(path, flags.gzip())?;
+
+  // ==========================================================================
+  render_header(&mut f, paths, PageTitle::Index, None)?;
+  writeln!(&mut f, "<main>")?;
+
+  // archived repositories are listed after all others if
+  // index.archived_section is set
+  let (archived, active): (Vec<_>, Vec<_>) = listed
+    .partition(|repo| config::ARCHIVED_SECTION && repo.archived);
+
+  // repositories are grouped by category, unless none of them has one: in
+  // which case they are listed in a single list, without any headings
+  if active.iter().any(|repo| repo.category.is_some()) {
+    let mut categories: Vec<(&str, Vec<&RepoInfo>)> = Vec::new();
+    for repo in active {
+      let category = repo.category.as_deref().unwrap_or(UNCATEGORIZED);
+      match categories.iter_mut().find(|(name, _)| *name == category) {
+        Some((_, repos)) => repos.push(repo),
+        None             => categories.push((category, vec![repo])),
+      }
+    }
+    // the order of the repositories within each category is preserved
+    categories.sort_by_key(|(name, _)| (*name == UNCATEGORIZED, name.to_lowercase()));
+
+    for (category, repos) in categories {
+      render_index_section(&mut f, Some(category), &repos, output_root)?;
+    }
+  } else {
+    render_index_section(&mut f, None, &active, output_root)?;
+  }
+
+  if !archived.is_empty() {
+    render_index_section(&mut f, Some("Archived"), &archived, output_root)?;
+  }
+
+  writeln!(&mut f, "</main>")?;
+  render_footer(&mut f)?;
+  writeln!(&mut f, "</body>")?;
+  writeln!(&mut f, "</html>")?;
+  f.finish()?;
+
+  render_robots_txt(paths, private)?;
+  render_global_feed(paths, repos, flags)?;
+  render_search_page(paths, repos, flags)?;
+  render_opensearch(paths, private)?;
+  install_assets(paths)
+}
+
+/// Renders `search.html` at the root of either the public or the private
+/// output, which searches the commits of all repositories listed in the index
+/// of the same output root
+///
+/// The page is static: the query is read from its `q` parameter and matched
+/// against the `search.json` index of the default branch of each repository.
+fn render_search_page(
+  paths: &Paths,
+  repos: &[RepoInfo],
+  flags: Flags,
+) -> io::Result<()> {
+  let private = flags.private();
+  let output_root = if private {
+    config::PRIVATE_OUTPUT_ROOT
+  } else {
+    ""
+  };
+
+  let mut path = paths.output_dir(private);
+  path.push("search.html");
+
+  let mut f = create_html_file(path, flags.gzip())?;
+
+  // ==========================================================================
+  render_header(&mut f, paths, PageTitle::Search, None)?;
+  writeln!(&mut f, "<main>")?;
+  writeln!(&mut f, "<h1>Search</h1>")?;
+  writeln!(&mut f, "<form action=\"/{root}search.html\">", root = Escaped(output_root))?;
+  writeln!(&mut f, "<input type=\"search\" id=\"search\" name=\"q\" placeholder=\"Search commits...\" aria-label=\"Search commits\" />")?;
+  writeln!(&mut f, "</form>")?;
+  writeln!(&mut f, "<div class=\"article-list\" id=\"results\"></div>")?;
+  writeln!(&mut f, "<script>")?;
+  write!(&mut f, "var repos = [")?;
+  for (i, repo) in repos.iter().filter(|repo| repo.private == private).enumerate() {
+    if i > 0 {
+      write!(&mut f, ",")?;
+    }
+    write!(&mut f, "\"{}\"", JsonEscaped(&repo.name))?;
+  }
+  writeln!(&mut f, "];")?;
+  writeln!(&mut f, "document.addEventListener(\"DOMContentLoaded\", function() {{")?;
+  writeln!(&mut f, "  var query = new URLSearchParams(location.search).get(\"q\");")?;
+  writeln!(&mut f, "  var results = document.getElementById(\"results\");")?;
+  writeln!(&mut f, "  if (!query) return;")?;
+  writeln!(&mut f, "  document.getElementById(\"search\").value = query;")?;
+  writeln!(&mut f, "  query = query.toLowerCase();")?;
+  writeln!(&mut f, "  repos.forEach(function(name) {{")?;
+  writeln!(&mut f, "    var root = \"/{root}\" + encodeURIComponent(name) + \"/\";",
+                   root = Escaped(output_root))?;
+  writeln!(&mut f, "    fetch(root + \"search.json\")")?;
+  writeln!(&mut f, "      .then(function(r) {{ return r.json(); }})")?;
+  writeln!(&mut f, "      .then(function(commits) {{")?;
+  writeln!(&mut f, "        commits.forEach(function(c) {{")?;
+  writeln!(&mut f, "          var text = (c.id + \" \" + c.summary + \" \" + c.author).toLowerCase();")?;
+  writeln!(&mut f, "          if (text.indexOf(query) === -1) return;")?;
+  writeln!(&mut f, "          var article = document.createElement(\"article\");")?;
+  // the commits past log.max_commits have no page to link to
+  writeln!(&mut f, "          var heading = document.createElement(c.page ? \"a\" : \"span\");")?;
+  writeln!(&mut f, "          if (c.page) heading.href = root + \"{COMMIT_SUBDIR}/\" + c.id + \".html\";")?;
+  writeln!(&mut f, "          heading.textContent = name + \": \" + c.id.slice(0, {len});",
+                   len = config::SHORT_ID_LEN)?;
+  writeln!(&mut f, "          var summary = document.createElement(\"p\");")?;
+  writeln!(&mut f, "          summary.textContent = c.summary + \" \\u2014 \" + c.author;")?;
+  writeln!(&mut f, "          article.appendChild(heading);")?;
+  writeln!(&mut f, "          article.appendChild(summary);")?;
+  writeln!(&mut f, "          results.appendChild(article);")?;
+  writeln!(&mut f, "        }});")?;
+  writeln!(&mut f, "      }});")?;
+  writeln!(&mut f, "  }});")?;
+  writeln!(&mut f, "}});")?;
+  writeln!(&mut f, "</script>")?;
+  writeln!(&mut f, "</main>")?;
+  render_footer(&mut f)?;
+  writeln!(&mut f, "</body>")?;
+  writeln!(&mut f, "</html>")?;
+  f.finish()
+}
+
+/// Writes an OpenSearch description to `opensearch.xml`, at the root of
+/// either the public or the private output, so that browsers can add the
+/// search page as a search engine
+///
+/// OpenSearch requires absolute URLs, so nothing is written unless
+/// `config::BASE_URL` is set.
+fn render_opensearch(paths: &Paths, private: bool) -> io::Result<()> {
+  let Some(base_url) = config::BASE_URL.map(|url| url.trim_end_matches('/')) else {
+    return Ok(());
+  };
+
+  let output_root = if private {
+    config::PRIVATE_OUTPUT_ROOT
+  } else {
+    ""
+  };
+
+  let mut path = paths.output_dir(private);
+  path.push("opensearch.xml");
+
+  let mut f = create_output(path)?;
+
+  writeln!(&mut f, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+  writeln!(&mut f, "<OpenSearchDescription xmlns=\"http://a9.com/-/spec/opensearch/1.1/\">")?;
+  writeln!(&mut f, "<ShortName>{}</ShortName>", PageTitle::Index)?;
+  writeln!(&mut f, "<Description>Search the commits of {}</Description>", PageTitle::Index)?;
+  writeln!(&mut f, "<InputEncoding>UTF-8</InputEncoding>")?;
+  writeln!(&mut f, "<Image type=\"image/svg+xml\">{}</Image>",
+                   Escaped(&absolute_url(base_url, config::FAVICON)))?;
+  writeln!(&mut f, "<Url type=\"text/html\" method=\"get\" template=\"{}\"/>",
+                   Escaped(&format!("{base_url}/{output_root}search.html?q={{searchTerms}}")))?;
+  writeln!(&mut f, "</OpenSearchDescription>")?;
+
+  f.flush()
+}
+
+/// Resolves `url` against `base_url`, unless it's already absolute
+fn absolute_url(base_url: &str, url: &str) -> String {
+  if url.contains("://") {
+    url.to_string()
+  } else {
+    format!("{base_url}/{}", url.trim_start_matches('/'))
+  }
+}
+
+/// Renders an Atom feed of the most recent commits across all of `repos` to
+/// `feed.xml`, at the root of either the public or the private output
+///
+/// Only the repositories listed in the index of the same output root are
+/// included, so that private commits never end up in the public feed. The
+/// commits are taken from the branch each repository was last rendered from,
+/// as recorded in its `info.json`, or from `HEAD` if it wasn't rendered yet.
+fn render_global_feed(
+  paths: &Paths,
+  repos: &[RepoInfo],
+  flags: Flags,
+) -> io::Result<()> {
+  let Some(max_entries) = config::FEED_MAX_ENTRIES else {
+    return Ok(());
+  };
+
+  let private = flags.private();
+  let output_root = if private {
+    config::PRIVATE_OUTPUT_ROOT
+  } else {
+    ""
+  };
+
+  // the N most recent commits of each repository, along with their position
+  // in its log: which tells whether they have a page of their own
+  let mut entries = Vec::new();
+  for repo in repos.iter().filter(|repo| repo.private == private) {
+    let mut info_path = paths.output_dir(private);
+    info_path.push(&repo.name);
+    info_path.push("info.json");
+    let branch = read_rendered_branch(&info_path);
+
+    // the rendered branch may have been deleted since
+    let head = find_branch(&repo.repo, branch.as_deref())
+      .or_else(|_| repo.repo.head())
+      .and_then(|reference| reference.peel_to_commit());
+    let Ok(head) = head else {
+      continue;
+    };
+    let Ok(mut revwalk) = repo.repo.revwalk() else {
+      continue;
+    };
+    if revwalk.push(head.id()).is_err() {
+      continue;
+    }
+
+    for (i, id) in revwalk.flatten().take(max_entries).enumerate() {
+      if let Ok(commit) = repo.repo.find_commit(id) {
+        entries.push((repo, i, commit));
+      }
+    }
+  }
+  entries.sort_by_key(|(_, _, commit)| cmp::Reverse(commit.author().when().seconds()));
+  entries.truncate(max_entries);
+
+  let Some((_, _, latest)) = entries.first() else {
+    return Ok(());
+  };
+
+  // Atom requires absolute ids, which can only be URLs if we know where the
+  // site is hosted
+  let base_url = config::BASE_URL.map(|url| url.trim_end_matches('/'));
+
+  let mut path = paths.output_dir(private);
+  path.push("feed.xml");
+  let mut f = create_output(path)?;
+
+  writeln!(&mut f, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+  writeln!(&mut f, "<feed xmlns=\"http://www.w3.org/2005/Atom\">")?;
+  writeln!(&mut f, "<title>{}</title>", PageTitle::Index)?;
+  if let Some(base_url) = base_url {
+    writeln!(&mut f, "<id>{}</id>",
+                     Escaped(&format!("{base_url}/{output_root}feed.xml")))?;
+    writeln!(&mut f, "<link href=\"{}\"/>",
+                     EscapedUrl(&format!("{base_url}/{output_root}")))?;
+  } else {
+    writeln!(&mut f, "<id>urn:yagit:{}feed</id>", Escaped(output_root))?;
+  }
+  writeln!(&mut f, "<updated>{}</updated>", Rfc3339(latest.author().when()))?;
+
+  for (repo, i, commit) in &entries {
+    let id = commit.id();
+    let sig = commit.author();
+    let summary = String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default());
+    let message = String::from_utf8_lossy(commit.message_bytes());
+
+    // older commits may not have a page of their own
+    let page = if config::MAX_COMMITS.is_none_or(|max| *i < max) {
+      format!("/{output_root}{name}/{COMMIT_SUBDIR}/{id}.html",
+              name = EscapedPath(&repo.name))
+    } else {
+      format!("/{output_root}{name}/{COMMIT_SUBDIR}/index.html",
+              name = EscapedPath(&repo.name))
+    };
+
+    writeln!(&mut f, "<entry>")?;
+    writeln!(&mut f, "<title>{repo}: {summary}</title>",
+                     repo = Escaped(&repo.name),
+                     summary = Escaped(summary.trim()))?;
+    if let Some(base_url) = base_url {
+      writeln!(&mut f, "<id>{base_url}/{output_root}{name}/{COMMIT_SUBDIR}/{id}.html</id>",
+                       base_url = Escaped(base_url),
+                       name = EscapedPath(&repo.name))?;
+      writeln!(&mut f, "<link href=\"{}{page}\"/>", Escaped(base_url))?;
+    } else {
+      writeln!(&mut f, "<id>urn:yagit:{name}:{id}</id>", name = EscapedPath(&repo.name))?;
+      writeln!(&mut f, "<link href=\"{page}\"/>")?;
+    }
+    writeln!(&mut f, "<updated>{}</updated>", Rfc3339(sig.when()))?;
+    writeln!(&mut f, "<author><name>{}</name></author>",
+                     Escaped(&String::from_utf8_lossy(sig.name_bytes())))?;
+    writeln!(&mut f, "<category term=\"{}\"/>", Escaped(&repo.name))?;
+    writeln!(&mut f, "<content type=\"text\">{}</content>", Escaped(message.trim()))?;
+    writeln!(&mut f, "</entry>")?;
+  }
+
+  writeln!(&mut f, "</feed>")?;
+  f.flush()
+}
+
+/// The heading of the repositories without a category in the index
+const UNCATEGORIZED: &str = "Uncategorized";
+
+/// Renders a list of repositories in the index of projects, under a heading
+/// if one is given
+fn render_index_section(
+  f: &mut OutputFile,
+  heading: Option<&str>,
+  repos: &[&RepoInfo],
+  output_root: &str,
+) -> io::Result<()> {
+  if let Some(heading) = heading {
+    writeln!(f, "<section>")?;
+    writeln!(f, "<h2>{}</h2>", Escaped(heading))?;
+  }
+
+  writeln!(f, "<div class=\"article-list\">")?;
+  for repo in repos {
+    render_index_entry(f, repo, output_root)?;
+  }
+  writeln!(f, "</div>")?;
+
+  if heading.is_some() {
+    writeln!(f, "</section>")?;
+  }
+
+  Ok(())
+}
+
+/// Renders the entry of `repo` in the index of projects
+fn render_index_entry(
+  f: &mut OutputFile,
+  repo: &RepoInfo,
+  output_root: &str,
+) -> io::Result<()> {
+  writeln!(f, "<article>")?;
+
+  writeln!(f, "<h4>")?;
+  writeln!(f, "<a href=\"/{root}{url}/index.html\">{repo}</a>",
+              root = output_root,
+              url = EscapedPath(&repo.name),
+              repo = Escaped(&repo.name))?;
+  writeln!(f, "</h4>")?;
+
+  writeln!(f, "<div>")?;
+  writeln!(f, "<span>{owner}</span>", owner = Escaped(&repo.owner))?;
+  writeln!(f, "<time datetime=\"{datetime}\">{date}</time>",
+              datetime  = DateTime(repo.last_commit),
+              date = Date(repo.last_commit))?;
+  writeln!(f, "</div>")?;
+
+  if let Some(ref description) = repo.description {
+    for p in description.trim().split("\n\n") {
+      writeln!(f, "<p>\n{p}\n</p>", p = Escaped(p.trim()))?;
+    }
+  } else if let Some(placeholder) = config::NO_DESCRIPTION {
+    writeln!(f, "<p class=\"notice\">{}</p>", Escaped(placeholder))?;
+  }
+
+  writeln!(f, "</article>")
+}
+
+/// The name of the manifest of generated files, relative to the output root
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// The hashes of the files written by this run, by their paths, if running
+/// with `--manifest`
+static WRITTEN_HASHES: Mutex<Option<HashMap<PathBuf, String>>> = Mutex::new(None);
+
+/// Sets whether the hashes of the files written should be recorded for the
+/// manifest, as with `--manifest`
+pub fn set_manifest(manifest: bool) {
+  *written_hashes() = manifest.then(HashMap::new);
+}
+
+fn written_hashes() -> MutexGuard<'static, Option<HashMap<PathBuf, String>>> {
+  WRITTEN_HASHES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn is_recording_hashes() -> bool {
+  written_hashes().is_some()
+}
+
+/// Records that `path` was written with contents hashed to `hash`
+fn record_hash(path: &Path, hash: String) {
+  if let Some(hashes) = written_hashes().as_mut() {
+    hashes.insert(path.to_path_buf(), hash);
+  }
+}
+
+/// Hashes the current contents of `path` for the manifest, for files which
+/// are part of the output of this run without having been written by it
+fn record_file_hash(path: &Path) -> io::Result<()> {
+  if !is_recording_hashes() || is_dry_run() {
+    return Ok(());
+  }
+
+  let mut hasher = Sha256::default();
+  let copied = File::open(path).and_then(|mut f| io::copy(&mut f, &mut hasher));
+  if let Err(e) = copied {
+    errorln!("Failed to hash {path:?}: {e}");
+    return Err(with_path(e, "hash", path));
+  }
+
+  record_hash(path, hasher.finish());
+  Ok(())
+}
+
+/// Writes a `manifest.json` at the root of the output directory, mapping the
+/// path of each generated file to the SHA-256 hash of its contents
+///
+/// Only the files written by this run are hashed: the hashes of all others
+/// are taken from the previous manifest. The whole output directory is only
+/// hashed if there's no previous manifest, or with `--full-build`. The pages
+/// of private repositories are left out of the public manifest.
+pub fn write_manifest(paths: &Paths, flags: Flags) -> io::Result<()> {
+  let private = flags.private();
+  let output_path = paths.output_dir(private);
+
+  let private_path = paths.output_dir(true);
+  let skip = if private { None } else { Some(private_path.as_path()) };
+
+  let manifest_path = output_path.join(MANIFEST_FILE);
+  let previous = if flags.full_build() {
+    None
+  } else {
+    read_manifest(&manifest_path)
+  };
+
+  let mut hashes = match previous {
+    // the files which were removed since are left out
+    Some(previous) => previous
+      .into_iter()
+      .filter(|(path, _)| output_path.join(path).exists())
+      .collect(),
+    None => {
+      let mut hashes = HashMap::new();
+      hash_files(&output_path, &output_path, skip, &mut hashes)?;
+      hashes
+    }
+  };
+
+  let written = written_hashes().clone().unwrap_or_default();
+  for (path, hash) in written {
+    if skip.is_some_and(|skip| path.starts_with(skip)) {
+      continue;
+    }
+    let Ok(rel_path) = path.strip_prefix(&output_path) else {
+      continue;
+    };
+    let Some(rel_path) = rel_path.to_str() else {
+      warnln!("Leaving {path:?} out of the manifest: its path is not valid UTF-8");
+      continue;
+    };
+
+    if is_listed_in_manifest(rel_path) {
+      hashes.insert(String::from(rel_path), hash);
+    }
+  }
+
+  let mut hashes: Vec<_> = hashes.into_iter().collect();
+  hashes.sort();
+
+  let mut f = create_output(manifest_path)?;
+  writeln!(&mut f, "{{")?;
+  for (i, (path, hash)) in hashes.iter().enumerate() {
+    let sep = if i + 1 < hashes.len() { "," } else { "" };
+    writeln!(&mut f, "  \"{path}\": \"{hash}\"{sep}", path = JsonEscaped(path))?;
+  }
+  writeln!(&mut f, "}}")?;
+  f.flush()?;
+
+  Ok(())
+}
+
+/// Checks if the file at `rel_path`, relative to the output root, should be
+/// listed in the manifest: which excludes the files yagit keeps for itself,
+/// such as the state of the last render
+fn is_listed_in_manifest(rel_path: &str) -> bool {
+  let rel_path = Path::new(rel_path);
+
+  rel_path != Path::new(MANIFEST_FILE)
+    && rel_path.file_name().is_some_and(|name| name != STATE_FILE)
+    && !rel_path.components().any(|c| c.as_os_str() == BLOBS_DIR)
+}
+
+/// Reads the hashes listed in the manifest at `path`, as written by
+/// [`write_manifest`]
+///
+/// Returns `None` if there's no manifest, or if it's malformed.
+fn read_manifest(path: &Path) -> Option<HashMap<String, String>> {
+  let manifest = fs::read_to_string(path).ok()?;
+
+  let mut hashes = HashMap::new();
+  for line in manifest.lines() {
+    let line = line.trim();
+    if line == "{" || line == "}" {
+      continue;
+    }
+
+    let Some((path, hash)) = parse_manifest_entry(line) else {
+      warnln!("Ignoring the malformed manifest at {path:?}");
+      return None;
+    };
+
+    hashes.insert(path, hash);
+  }
+
+  Some(hashes)
+}
+
+/// Parses a `"path": "hash"` line of the manifest
+fn parse_manifest_entry(line: &str) -> Option<(String, String)> {
+  let (path, rest) = parse_json_str(line)?;
+  let (hash, rest) = parse_json_str(rest.strip_prefix(": ")?)?;
+  matches!(rest, "" | ",").then_some((path, hash))
+}
+
+/// Parses the JSON string at the start of `s`, as written by [`JsonEscaped`],
+/// returning it along with the rest of `s`
+fn parse_json_str(s: &str) -> Option<(String, &str)> {
+  let s = s.strip_prefix('"')?;
+  let mut chars = s.char_indices();
+
+  let mut result = String::new();
+  loop {
+    match chars.next()? {
+      (i, '"') => return Some((result, &s[i+1..])),
+      (_, '\\') => match chars.next()?.1 {
+        '"'  => result.push('"'),
+        '\\' => result.push('\\'),
+        'n'  => result.push('\n'),
+        'r'  => result.push('\r'),
+        't'  => result.push('\t'),
+        'u'  => {
+          let code = chars.by_ref().take(4).map(|(_, c)| c).collect::<String>();
+          result.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+        }
+        _ => return None,
+      },
+      (_, c) => result.push(c),
+    }
+  }
+}
+
+/// Hashes the files in `dir`, recursively, inserting their paths relative to
+/// `root` along with their hashes into `hashes`
+fn hash_files(
+  dir: &Path,
+  root: &Path,
+  skip: Option<&Path>,
+  hashes: &mut HashMap<String, String>,
+) -> io::Result<()> {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    // the directory would have been created if not for --dry-run
+    Err(e) if e.kind() == io::ErrorKind::NotFound && is_dry_run() => {
+      return Ok(());
+    }
+    Err(e) => {
+      errorln!("Could not read {dir:?}: {e}");
+      return Err(with_path(e, "read", dir));
+    }
+  };
+
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if skip.is_some_and(|skip| path == skip) || entry.file_name() == BLOBS_DIR {
+      continue;
+    }
+
+    // symlinks to deduplicated blobs are hashed as the files they point to
+    if path.is_dir() {
+      hash_files(&path, root, skip, hashes)?;
+      continue;
+    }
+
+    let rel_path = path.strip_prefix(root).expect("path should be inside the output root");
+    let Some(rel_path) = rel_path.to_str() else {
+      warnln!("Leaving {path:?} out of the manifest: its path is not valid UTF-8");
+      continue;
+    };
+    if !is_listed_in_manifest(rel_path) {
+      continue;
+    }
+
+    let mut hasher = Sha256::default();
+    let copied = File::open(&path).and_then(|mut f| io::copy(&mut f, &mut hasher));
+    if let Err(e) = copied {
+      errorln!("Failed to hash {path:?}: {e}");
+      return Err(with_path(e, "hash", &path));
+    }
+
+    hashes.insert(String::from(rel_path), hasher.finish());
+  }
+
+  Ok(())
+}
+
+/// The default assets linked from the header of every page, as
+/// `(file name, configured URL, contents)` triples
+const ASSETS: &[(&str, &str, &[u8])] = &[
+  ("styles.css",  config::STYLESHEET, include_bytes!("../assets/styles.css")),
+  ("favicon.svg", config::FAVICON,    include_bytes!("../assets/favicon.svg")),
+];
+
+/// Writes the default assets to the root of the output, so that a fresh
+/// deployment doesn't have broken links
+///
+/// Assets which already exist are left alone, since they may have been
+/// customized by the user. So are the ones whose URL was changed in the
+/// configuration.
+fn install_assets(paths: &Paths) -> io::Result<()> {
+  for (name, url, contents) in ASSETS {
+    if url.strip_prefix('/') != Some(name) {
+      continue;
+    }
+
+    let mut path = paths.output.clone();
+    path.push(name);
+
+    if path.exists() {
+      continue;
+    }
+
+    infoln!("Installing the default {name}");
+    let mut f = create_output(&path)?;
+    if let Err(e) = f.write_all(contents).and_then(|_| f.flush()) {
+      errorln!("Failed to write {path:?}: {e}");
+      return Err(e);
+    }
+  }
+
+  Ok(())
+}
+
+/// Writes a `robots.txt` file to the root of the output
+///
+/// Crawlers are disallowed from indexing anything in the private output root.
+/// The public `robots.txt` is only written if `config::ROBOTS_TXT` is set, so
+/// that we don't overwrite one provided by the user.
+fn render_robots_txt(paths: &Paths, private: bool) -> io::Result<()> {
+  if !private && !config::ROBOTS_TXT {
+    return Ok(());
+  }
+
+  let mut path = paths.output_dir(private);
+  path.push("robots.txt");
+
+  let mut f = create_output(path)?;
+
+  writeln!(&mut f, "User-agent: *")?;
+  if private {
+    writeln!(&mut f, "Disallow: /")?;
+  } else {
+    // crawlers only look for robots.txt at the root of the website, so we
+    // should also disallow the private pages here
+    writeln!(&mut f, "Disallow: /{PRIVATE_OUTPUT_ROOT}")?;
+    writeln!(&mut f, "Allow: /")?;
+  }
+
+  f.flush()
+}
+
+/// Writes the owner, description and `post-update` hook of a repository
+/// freshly initialized in `path`
+pub fn setup_repo(
+  name: &str,
+  path: &Path,
+  description: &str,
+  private: bool,
+  bare: bool,
+) -> io::Result<()> {
+  let mut path = path.to_path_buf();
+  if !bare { path.push(".git"); }
+
+  // ==========================================================================
+  let mut owner_path = path.clone();
+  owner_path.push("owner");
+
+  let mut owner_f = create_file(owner_path)?;
+
+  write!(&mut owner_f, "{}", config::OWNER.trim())?;
+
+  // ==========================================================================
+  let mut dsc_path = path.clone();
+  dsc_path.push("description");
+
+  let mut dsc_f = create_file(dsc_path)?;
+
+  write!(&mut dsc_f, "{}", description)?;
+
+  // ==========================================================================
+  let mut hook_path = path.clone();
+  hook_path.push("hooks");
+  hook_path.push("post-update");
+
+  let mut hook_f = create_file(&hook_path)?;
+
+  writeln!(&mut hook_f, "#!/bin/sh")?;
+  if private {
+    writeln!(&mut hook_f, "yagit --private render {name:?}")?;
+  } else {
+    writeln!(&mut hook_f, "yagit render {name:?}")?;
+  }
+
+  const HOOK_MODE: u32 = 0o755;
+  let mut mode = hook_f.metadata()?.permissions();
+  mode.set_mode(HOOK_MODE);
+
+  drop(hook_f);
+  if let Err(e) = fs::set_permissions(&hook_path, mode) {
+    errorln!("Failed set permissions to {hook_path:?}: {e}");
+    return Err(with_path(e, "set the permissions of", &hook_path));
+  }
+
+  // ==========================================================================
+  // make it possible to push to the repo, eventhough it's not a bare repo
+  if bare {
+    return Ok(());
+  }
+
+  let mut config_path = path;
+  config_path.push("config");
+
+  let mut config_opts = fs::OpenOptions::new();
+  config_opts.append(true).create(true);
+
+  let mut config_f = match config_opts.open(&config_path) {
+    Ok(f)  => f,
+    Err(e) => {
+      errorln!("Failed to create {config_path:?}: {e}");
+      return Err(with_path(e, "create", &config_path));
+    }
+  };
+
+  writeln!(&mut config_f, "[receive]")?;
+  writeln!(&mut config_f, "\tdenyCurrentBranch = updateInstead")?;
+
+  Ok(())
+}
+
+/// Abbreviates `id` to `config::SHORT_ID_LEN` hex digits, or more if needed for
+/// it to be unambiguous among the objects in `odb`
+///
+/// Falls back to the minimum length if the object database couldn't be read.
+fn short_id(odb: Option<&Odb<'_>>, id: Oid) -> String {
+  let id_str = id.to_string();
+  let len = (config::SHORT_ID_LEN..id_str.len())
+    .find(|&len| odb.is_none_or(|odb| odb.exists_prefix(id, len).is_ok()))
+    .unwrap_or(id_str.len());
+
+  id_str[..len].to_string()
+}
+
+/// Known git hosts, along with the path of the page of a commit relative to
+/// the URL of a repository
+const GIT_HOSTS: &[(&str, &str)] = &[
+  ("github.com",    "commit"),
+  ("gitlab.com",    "-/commit"),
+  ("codeberg.org",  "commit"),
+  ("git.sr.ht",     "commit"),
+  ("bitbucket.org", "commits"),
+];
+
+/// Returns the URL of the page of commit `id` of the repository at `url`, if
+/// it's hosted at one of the [`GIT_HOSTS`]
+///
+/// Both HTTP(S) and SSH URLs are supported, such as
+/// `https://github.com/user/repo.git` and `git@github.com:user/repo.git`.
+fn hosted_commit_url(url: &str, id: Oid) -> Option<String> {
+  let rest = url
+    .strip_prefix("https://")
+    .or_else(|| url.strip_prefix("http://"))
+    .or_else(|| url.strip_prefix("ssh://"))
+    .or_else(|| url.strip_prefix("git://"))
+    .unwrap_or(url);
+  let rest = rest.split_once('@').map_or(rest, |(_, rest)| rest);
+
+  // scp-like SSH URLs separate the host from the path with a colon
+  let (host, repo_path) = rest.split_once(['/', ':'])?;
+  let repo_path = repo_path.trim_end_matches('/');
+  let repo_path = repo_path.strip_suffix(".git").unwrap_or(repo_path);
+  if repo_path.is_empty() {
+    return None;
+  }
+
+  let (host, commit_path) = GIT_HOSTS.iter().find(|(h, _)| *h == host)?;
+  Some(format!("https://{host}/{repo_path}/{commit_path}/{id}"))
+}
+
+/// Returns the origin marker, the CSS class and the line number of the anchor
+/// of an added or deleted line in a diff
+///
+/// Returns `None` for all other lines, such as context lines.
+fn diff_line_anchor(line: &DiffLine<'_>) -> Option<(char, &'static str, u32)> {
+  match line.origin_value() {
+    DiffLineType::Addition => Some(('+', "i", line.new_lineno()?)),
+    DiffLineType::Deletion => Some(('-', "d", line.old_lineno()?)),
+    _                      => None,
+  }
+}
+
+/// Checks if the web browser can display a binary file by itself, based on its
+/// extension
+fn is_viewable_inline(path: &Path) -> bool {
+  const INLINE_FILE_EXTS: &[&str] = &[
+    "pdf",
+    "png", "jpg", "jpeg", "gif", "webp", "svg", "ico",
+    "mp3", "ogg", "wav", "flac",
+    "mp4", "webm",
+  ];
+
+  path
+    .extension()
+    .is_some_and(|ext| {
+      INLINE_FILE_EXTS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+    })
+}
+
+/// The content of a blob
+///
+/// libgit2 can only stream loose objects out of the object database: packed
+/// objects are loaded in memory instead, as are loose objects if the object
+/// database can't be streamed from for some reason.
+enum BlobContent<'repo> {
+  Loose {
+    odb:  &'repo Odb<'repo>,
+    id:   Oid,
+    size: usize,
+  },
+  Loaded(git2::Blob<'repo>),
+}
+
+impl<'repo> BlobContent<'repo> {
+  fn open(repo: &'repo Repository, odb: &'repo Odb<'repo>, id: Oid) -> Self {
+    match odb.reader(id) {
+      Ok((_, size, _)) => Self::Loose { odb, id, size },
+      Err(_) => {
+        let blob = repo.find_blob(id).expect("blob should be in the repository");
+        Self::Loaded(blob)
+      }
+    }
+  }
+
+  fn size(&self) -> usize {
+    match self {
+      Self::Loose { size, .. } => *size,
+      Self::Loaded(blob)       => blob.size(),
+    }
+  }
+
+  /// Returns a new reader over the content of the blob, starting from the
+  /// beginning
+  fn reader(&self) -> Box<dyn BufRead + '_> {
+    match self {
+      Self::Loose { odb, id, .. } => {
+        let (reader, _, _) = odb.reader(*id).expect("blob should be in the object database");
+        Box::new(io::BufReader::new(reader))
+      }
+      Self::Loaded(blob) => Box::new(blob.content()),
+    }
+  }
+
+  /// Checks whether Git would consider the blob binary, which only depends
+  /// on its first few kilobytes
+  fn is_binary(&self) -> io::Result<bool> {
+    match self {
+      Self::Loose { .. } => {
+        let mut prefix = Vec::with_capacity(BINARY_CHECK_LEN);
+        self.reader().take(BINARY_CHECK_LEN as u64).read_to_end(&mut prefix)?;
+        Ok(looks_binary(&prefix))
+      }
+      Self::Loaded(blob) => Ok(blob.is_binary()),
+    }
+  }
+}
+
+/// The number of bytes at the start of a file Git looks at to determine if
+/// it's binary
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// Checks whether Git would consider a file starting with `prefix` binary,
+/// following the heuristic of `git_blob_is_binary`: files with a NUL byte,
+/// or with too many non-printable characters, are binary
+fn looks_binary(prefix: &[u8]) -> bool {
+  let prefix = &prefix[..cmp::min(prefix.len(), BINARY_CHECK_LEN)];
+
+  // files with UTF-16 or UTF-32 byte order marks are binary as far as Git is
+  // concerned, whereas the UTF-8 BOM is simply skipped
+  const OTHER_BOMS: &[&[u8]] = &[b"\xff\xfe", b"\xfe\xff", b"\0\0\xfe\xff"];
+  if OTHER_BOMS.iter().any(|bom| prefix.starts_with(bom)) {
+    return true;
+  }
+  let prefix = prefix.strip_prefix(b"\xef\xbb\xbf").unwrap_or(prefix);
+
+  let mut printable = 0;
+  let mut nonprintable = 0;
+  for &c in prefix {
+    if (c > 0x1f && c != 0x7f) || matches!(c, b'\x08' | b'\x1b' | b'\x0c') {
+      printable += 1;
+    } else if c == 0 {
+      return true;
+    } else if !matches!(c, b' ' | b'\t'..=b'\r') {
+      nonprintable += 1;
+    }
+  }
+
+  (printable >> 7) < nonprintable
+}
+
+/// Writes the content read by the readers returned by `open` to `path`,
+/// unless `path` already has the exact same contents
+///
+/// Unlike [`write_if_changed`], neither the contents of the file nor the new
+/// contents are loaded in memory all at once. Returns whether the file was
+/// written to.
+fn copy_if_changed<R: Read>(
+  path: &Path,
+  size: usize,
+  open: impl Fn() -> R,
+) -> io::Result<bool> {
+  // links should have been removed with remove_blob_link, since writing to
+  // them would change the file they point to
+  let unchanged = fs::symlink_metadata(path)
+    .is_ok_and(|m| m.is_file() && m.len() == size as u64)
+    && File::open(path).is_ok_and(|old| {
+      same_content(io::BufReader::new(old), io::BufReader::new(open()))
+    });
+  if unchanged {
+    record_file_hash(path)?;
+    return Ok(false);
+  }
+
+  let mut f = create_output(path)?;
+  if let Err(e) = io::copy(&mut open(), &mut f).and_then(|_| f.flush()) {
+    errorln!("Failed to write {path:?}: {e}");
+    return Err(e);
+  }
+
+  Ok(true)
+}
+
+/// Removes the entries of `dir` which aren't the output directory of any of
+/// `branches`, where `prefix` is the part of the names of the branches `dir`
+/// stands for: since the names of branches may contain slashes
+fn remove_stale_branch_dirs(dir: &Path, prefix: &str, branches: &[&str]) -> io::Result<()> {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+    Err(e) => {
+      errorln!("Could not read {dir:?}: {e}");
+      return Err(with_path(e, "read", dir));
+    }
+  };
+
+  for entry in entries.flatten() {
+    let Some(name) = entry.file_name().to_str().map(|name| format!("{prefix}{name}")) else {
+      continue;
+    };
+    if branches.contains(&name.as_str()) {
+      continue;
+    }
+
+    let path = entry.path();
+    let is_parent = branches
+      .iter()
+      .any(|branch| branch.strip_prefix(&name).is_some_and(|rest| rest.starts_with('/')));
+    if is_parent {
+      remove_stale_branch_dirs(&path, &format!("{name}/"), branches)?;
+      continue;
+    }
+
+    if is_dry_run() {
+      infoln!("Would remove the pages of deleted branch {name:?}");
+      continue;
+    }
+
+    infoln!("Removing the pages of deleted branch {name:?}");
+    let removed = if path.is_dir() {
+      fs::remove_dir_all(&path)
+    } else {
+      fs::remove_file(&path)
+    };
+    if let Err(e) = removed {
+      errorln!("Failed to remove {path:?}: {e}");
+      return Err(with_path(e, "remove", &path));
+    }
+  }
+
+  Ok(())
+}
+
+/// Removes `path` if it's linked to the deduplicated copy of a blob, so that
+/// writing a regular copy to it doesn't change the shared copy
+fn remove_blob_link(path: &Path) -> io::Result<()> {
+  let is_link = fs::symlink_metadata(path)
+    .is_ok_and(|m| !m.is_file() || m.nlink() > 1);
+  if !is_link || is_dry_run() {
+    return Ok(());
+  }
+
+  if let Err(e) = fs::remove_file(path) {
+    errorln!("Failed to remove {path:?}: {e}");
+    return Err(with_path(e, "remove", path));
+  }
+
+  Ok(())
+}
+
+/// Compares the contents read by `a` and `b` chunk by chunk
+fn same_content<A: BufRead, B: BufRead>(mut a: A, mut b: B) -> bool {
+  loop {
+    let (Ok(chunk_a), Ok(chunk_b)) = (a.fill_buf(), b.fill_buf()) else {
+      return false;
+    };
+    if chunk_a.is_empty() || chunk_b.is_empty() {
+      return chunk_a.is_empty() && chunk_b.is_empty();
+    }
+
+    let n = cmp::min(chunk_a.len(), chunk_b.len());
+    if chunk_a[..n] != chunk_b[..n] {
+      return false;
+    }
+    a.consume(n);
+    b.consume(n);
+  }
+}
+
+/// Determines wether or not a file is binary based on `path` and on what Git
+/// reports: this is needed because Git sometimes reports PDF files as
+/// non-binary files
+fn is_binary(path: &Path, git_is_binary: bool) -> bool {
+  const BINARY_FILE_EXTS: &[&str] = &["pdf", "bin"];
+  let mut is_binary = git_is_binary;
+
+  if let Some(ext) = path.extension() {
+    is_binary |= BINARY_FILE_EXTS.contains(&ext.to_string_lossy().as_ref());
+  }
+
+  is_binary
+}
+
+/// Whether a repository in the public store was marked as private, either with
+/// a `.git/private` file or with the `yagit.private` option, in which case its
+/// pages are rendered under the private output root
+pub fn is_marked_private(repo: &Repository) -> bool {
+  repo.path().join("private").exists() || repo
+    .config()
+    .and_then(|config| config.get_bool("yagit.private"))
+    .unwrap_or(false)
+}
+
+/// Whether a repository was marked as archived, either with a `.git/archived`
+/// file or with the `yagit.archived` option
+fn is_marked_archived(repo: &Repository) -> bool {
+  repo.path().join("archived").exists() || repo
+    .config()
+    .and_then(|config| config.get_bool("yagit.archived"))
+    .unwrap_or(false)
+}
+
+/// Returns the value of the first of `keys` set in the configuration of `repo`
+fn git_config_str(repo: &Repository, keys: &[&str]) -> Option<String> {
+  let config = repo.config().ok()?;
+  keys.iter().find_map(|key| config.get_string(key).ok())
+}
+
+fn create_file<P: AsRef<Path> + fmt::Debug>(path: P) -> io::Result<File> {
+  File::create(&path)
+    .map_err(|e| { errorln!("Failed to create {:?}: {e}", &path); with_path(e, "create", &path) })
+}
+
+/// Whether running with `--dry-run` enabled, in which case nothing is written
+/// to the output directory
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether nothing should be written to the output directory, as with
+/// `--dry-run`
+pub fn set_dry_run(dry_run: bool) {
+  DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+fn is_dry_run() -> bool {
+  DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Creates a generated file at `path`
+///
+/// Writes to the file are buffered, so it should be flushed once everything is
+/// written: errors are silently ignored when it's flushed on drop. If running
+/// with `--dry-run` enabled, logs the path instead and returns a sink which
+/// discards everything written to it.
+fn create_output<P: AsRef<Path> + fmt::Debug>(
+  path: P,
+) -> io::Result<Box<dyn Write>> {
+  if is_dry_run() {
+    infoln!("Would write {path:?}");
+    return Ok(Box::new(io::sink()));
+  }
+
+  let f = create_file(&path)?;
+  set_mode(&path, config::FILE_MODE)?;
+
+  // write errors are reported along with the path of the file
+  let f = PathWriter::new(io::BufWriter::new(f), &path);
+  if is_recording_hashes() {
+    let path = path.as_ref().to_path_buf();
+    Ok(Box::new(HashingWriter { inner: f, hasher: Sha256::default(), path }))
+  } else {
+    Ok(Box::new(f))
+  }
+}
+
+/// A writer which hashes everything written to it, recording the hash for the
+/// manifest whenever it's flushed
+struct HashingWriter<W> {
+  inner:  W,
+  hasher: Sha256,
+  path:   PathBuf,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let n = self.inner.write(buf)?;
+    self.hasher.update(&buf[..n]);
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()?;
+    record_hash(&self.path, self.hasher.clone().finish());
+    Ok(())
+  }
+}
+
+/// Sets the permissions of `path` to `mode`, if one was configured
+fn set_mode<P: AsRef<Path> + fmt::Debug>(
+  path: P,
+  mode: Option<u32>,
+) -> io::Result<()> {
+  let Some(mode) = mode else {
+    return Ok(());
+  };
+
+  if let Err(e) = fs::set_permissions(&path, fs::Permissions::from_mode(mode)) {
+    errorln!("Failed set permissions to {path:?}: {e}");
+    return Err(with_path(e, "set the permissions of", &path));
+  }
+
+  Ok(())
+}
+
+/// Opens `repo` to look up the attributes of the files of the commit `id`
+fn open_attributes(repo: &Repository, name: &str, id: Oid) -> Option<Attributes> {
+  let attributes = Attributes::open(repo.path(), id);
+  if attributes.is_none() {
+    warnln!("Could not read the attributes of {name:?}: files marked with export-ignore won't be hidden");
+  }
+  attributes
+}
+
+/// Returns the reference to the branch pages are rendered from: `branch`, if
+/// given, or `HEAD`
+fn find_branch<'repo>(
+  repo: &'repo Repository,
+  branch: Option<&str>,
+) -> Result<Reference<'repo>, git2::Error> {
+  match branch {
+    Some(branch) => repo
+      .find_branch(branch, BranchType::Local)
+      .map(|branch| branch.into_reference()),
+    None => repo.head(),
+  }
+}
+
+/// Checks if the file at `path` in `tree` has a page of its own: i.e. if it's
+/// a blob other than a symlink which isn't hidden with export-ignore
+fn has_blob_page(tree: &Tree, attributes: Option<&Attributes>, path: &Path) -> bool {
+  let is_blob = tree.get_path(path).is_ok_and(|entry| {
+    entry.kind() == Some(ObjectType::Blob) && !Mode(entry.filemode()).is_symlink()
+  });
+
+  // the files in directories marked with export-ignore are hidden too
+  let is_hidden = || {
+    path
+      .ancestors()
+      .take_while(|path| !path.as_os_str().is_empty())
+      .any(|path| attributes.is_some_and(|a| a.is_set(path, "export-ignore")))
+  };
+
+  is_blob && !is_hidden()
+}
+
+/// Returns the files which had a page of their own in the tree of any of the
+/// branches rendered last time, as recorded in `state`, but which don't have
+/// one anymore: either because they were removed or hidden, or because their
+/// branch was deleted
+///
+/// Returns `None` if the tree some branch pointed to can't be found anymore,
+/// e.g. because the branch was force-pushed and the repository was pruned.
+fn removed_paths(
+  repo: &Repository,
+  state: &State,
+  branches: &[String],
+) -> Option<HashSet<PathBuf>> {
+  let mut result = HashSet::new();
+
+  for (branch, id) in state.heads() {
+    let old_tree = repo.find_commit(id).and_then(|commit| commit.tree()).ok()?;
+    let new_head = if branches.iter().any(|b| b == branch) {
+      find_branch(repo, Some(branch)).and_then(|reference| reference.peel_to_commit()).ok()
+    } else {
+      None
+    };
+
+    // all the pages of a deleted branch are removed
+    let Some(new_head) = new_head else {
+      result.extend(blob_paths(&old_tree)?);
+      continue;
+    };
+    if new_head.id() == id {
+      continue;
+    }
+
+    let new_tree = new_head.tree().ok()?;
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None).ok()?;
+
+    // a change to the .gitattributes files may hide any of the files, not
+    // just the ones which changed
+    let attributes_changed = diff.deltas().any(|delta| {
+      [delta.old_file(), delta.new_file()]
+        .iter()
+        .any(|file| file.path().is_some_and(|path| path.ends_with(".gitattributes")))
+    });
+    let candidates = if attributes_changed {
+      blob_paths(&old_tree)?
+    } else {
+      diff
+        .deltas()
+        .filter_map(|delta| delta.old_file().path().map(Path::to_path_buf))
+        .collect()
+    };
+
+    let old_attributes = Attributes::open(repo.path(), id);
+    let new_attributes = Attributes::open(repo.path(), new_head.id());
+    for path in candidates {
+      if has_blob_page(&old_tree, old_attributes.as_ref(), &path)
+        && !has_blob_page(&new_tree, new_attributes.as_ref(), &path) {
+        result.insert(path);
+      }
+    }
+  }
+
+  Some(result)
+}
+
+/// Returns the paths of all blobs in `tree`, recursively
+fn blob_paths(tree: &Tree) -> Option<Vec<PathBuf>> {
+  let mut result = Vec::new();
+  tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+    if entry.kind() == Some(ObjectType::Blob) {
+      result.push(Path::new(dir).join(entry.name().unwrap_or_default()));
+    }
+    TreeWalkResult::Ok
+  }).ok()?;
+
+  Some(result)
+}
+
+/// Returns the name, the id of the tip commit and the tree of a branch
+fn resolve_branch<'repo>(
+  reference: &Reference<'repo>,
+) -> (String, Oid, Tree<'repo>) {
+  let branch = reference
+    .shorthand()
+    .expect("should be able to get branch shorthand")
+    .to_string();
+  let head_id = reference
+    .peel_to_commit()
+    .expect("branch should point to a commit")
+    .id();
+  let head = reference
+    .peel_to_tree()
+    .expect("branch should point to a tree");
+
+  (branch, head_id, head)
+}
+
+/// Reads the dates of the first and last commits of a repository from the
+/// `info.json` written by [`RepoRenderer::render_json`]
+fn read_rendered_dates(path: &Path) -> Option<(u32, Time)> {
+  let content = fs::read_to_string(path).ok()?;
+
+  let field = |key: &str| -> Option<i64> {
+    content.lines().find_map(|line| {
+      let value = line.trim().strip_prefix(&format!("\"{key}\": "))?;
+      value.trim_end_matches(',').parse().ok()
+    })
+  };
+
+  let first_commit = field("first_commit")?;
+  let last_commit = field("last_commit")?;
+  let offset = field("last_commit_offset")?;
+
+  Some((first_commit as u32, Time::new(last_commit, offset as i32)))
+}
+
+/// Reads the branch a repository was rendered from from the `info.json`
+/// written by [`RepoRenderer::render_json`]
+fn read_rendered_branch(path: &Path) -> Option<String> {
+  let content = fs::read_to_string(path).ok()?;
+  content.lines().find_map(|line| {
+    let value = line.trim().strip_prefix("\"branch\": ")?;
+    let (branch, _) = parse_json_str(value)?;
+    Some(branch)
+  })
+}
+
+/// Returns the tree `branch` pointed to when the repository was last rendered
+fn previous_tree<'repo>(
+  repo: &'repo Repository,
+  state: Option<&State>,
+  branch: &str,
+) -> Option<Tree<'repo>> {
+  // the commit may have been garbage-collected after a force-push
+  let id = state?.head(branch)?;
+  repo.find_commit(id).ok()?.tree().ok()
+}
+
+/// An HTML page being written to disk
+///
+/// The page is buffered in memory and only written to disk by
+/// [`OutputFile::finish`], which leaves the file untouched if its contents
+/// haven't changed: this preserves the modification time of pages which are
+/// regenerated but byte-identical, which web servers use to answer
+/// `If-Modified-Since` requests.
+///
+/// If running with `--gzip`, a gzip-compressed copy of the page is also
+/// written at `{path}.gz`, which can be served directly by the web server.
+struct OutputFile {
+  path: PathBuf,
+  buff: Vec<u8>,
+  gzip: bool,
+}
+
+impl OutputFile {
+  fn finish(self) -> io::Result<()> {
+    let changed = write_if_changed(&self.path, &self.buff)?;
+
+    if self.gzip {
+      let mut gz_path = self.path.into_os_string();
+      gz_path.push(".gz");
+      let gz_path = PathBuf::from(gz_path);
+
+      if changed || !gz_path.exists() {
+        let mut gz = GzEncoder::new(create_output(&gz_path)?, Compression::best());
+        let written = gz
+          .write_all(&self.buff)
+          .and_then(|_| gz.try_finish())
+          .and_then(|_| gz.get_mut().flush());
+        if let Err(e) = written {
+          errorln!("Failed to write {gz_path:?}: {e}");
+          return Err(e);
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl Write for OutputFile {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.buff.extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+fn create_html_file<P: AsRef<Path> + fmt::Debug>(
+  path: P,
+  gzip: bool,
+) -> io::Result<OutputFile> {
+  Ok(OutputFile { path: path.as_ref().to_path_buf(), buff: Vec::new(), gzip, })
+}
+
+/// Writes `bytes` to `path`, unless `path` already has the exact same contents
+///
+/// Returns whether the file was written to.
+fn write_if_changed(path: &Path, bytes: &[u8]) -> io::Result<bool> {
+  let unchanged = fs::metadata(path)
+    .is_ok_and(|m| m.len() == bytes.len() as u64)
+    && fs::read(path).is_ok_and(|old| old == bytes);
+  if unchanged {
+    if is_recording_hashes() {
+      let mut hasher = Sha256::default();
+      hasher.update(bytes);
+      record_hash(path, hasher.finish());
+    }
+    return Ok(false);
+  }
+
+  let mut f = create_output(path)?;
+  if let Err(e) = f.write_all(bytes).and_then(|_| f.flush()) {
+    errorln!("Failed to write {path:?}: {e}");
+    return Err(e);
+  }
+
+  Ok(true)
+}
+
+fn create_dir<P: AsRef<Path> + fmt::Debug>(path: P) -> io::Result<()> {
+  if is_dry_run() {
+    infoln!("Would create {path:?}");
+    return Ok(());
+  }
+
+  if let Err(e) = fs::create_dir(&path) {
+    errorln!("Failed to create {:?}: {e}", &path);
+    return Err(with_path(e, "create", &path));
+  }
+
+  #[cfg(not(debug_assertions))]
+  if let Err(e) = unix::fs::chown(&path, None, Some(config::GROUP_ID)) {
+    errorln!("Failed to configure the user group for {:?}: {e}", &path);
+    return Err(with_path(e, "configure the user group of", &path));
+  }
+
+  set_mode(&path, config::DIR_MODE)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+
+  use super::*;
+
+  /// A bare repository in a temporary directory, removed when dropped
+  struct TempRepo {
+    path: PathBuf,
+    repo: Repository,
+  }
+
+  impl TempRepo {
+    fn new(name: &str) -> Self {
+      let mut path = env::temp_dir();
+      path.push(format!("yagit-test-{}-{name}", std::process::id()));
+      let _ = fs::remove_dir_all(&path);
+
+      let repo = Repository::init_bare(&path).unwrap();
+      Self { path, repo, }
+    }
+
+    fn tree_with_file(&self, name: &str, content: &str) -> Tree<'_> {
+      let blob = self.repo.blob(content.as_bytes()).unwrap();
+      let mut builder = self.repo.treebuilder(None).unwrap();
+      builder.insert(name, blob, 0o100644).unwrap();
+      self.repo.find_tree(builder.write().unwrap()).unwrap()
+    }
+  }
+
+  impl Drop for TempRepo {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.path);
+    }
+  }
+
+  /// The anchors of the lines in each hunk of the first delta of a diff
+  fn anchors(
+    repo: &Repository,
+    old: Option<&Tree<'_>>,
+    new: Option<&Tree<'_>>,
+  ) -> Vec<(usize, char, &'static str, u32)> {
+    let mut opts = DiffOptions::new();
+    opts.context_lines(1);
+    let diff = repo.diff_tree_to_tree(old, new, Some(&mut opts)).unwrap();
+    let patch = Patch::from_diff(&diff, 0).unwrap().unwrap();
+
+    let mut anchors = Vec::new();
+    for hunk_id in 0..patch.num_hunks() {
+      for line_id in 0..patch.num_lines_in_hunk(hunk_id).unwrap() {
+        let line = patch.line_in_hunk(hunk_id, line_id).unwrap();
+        if let Some((origin, class, lineno)) = diff_line_anchor(&line) {
+          anchors.push((hunk_id, origin, class, lineno));
+        }
+      }
+    }
+
+    anchors
+  }
+
+  #[test]
+  fn added_file_anchors_use_file_line_numbers() {
+    let repo = TempRepo::new("added");
+    let content: String = (1..=20).map(|n| format!("line {n}\n")).collect();
+    let tree = repo.tree_with_file("file.txt", &content);
+
+    let anchors = anchors(&repo.repo, None, Some(&tree));
+    let expected: Vec<_> = (1..=20).map(|n| (0, '+', "i", n)).collect();
+    assert_eq!(anchors, expected);
+  }
+
+  #[test]
+  fn multi_hunk_anchors_are_unique() {
+    let repo = TempRepo::new("multi-hunk");
+    let old: String = (1..=20).map(|n| format!("line {n}\n")).collect();
+    let new = old
+      .replace("line 2\n", "line two\n")
+      .replace("line 15\n", "line fifteen\nline fifteen and a half\n");
+    let old = repo.tree_with_file("file.txt", &old);
+    let new = repo.tree_with_file("file.txt", &new);
+
+    let anchors = anchors(&repo.repo, Some(&old), Some(&new));
+    assert_eq!(anchors, [
+      (0, '-', "d", 2),
+      (0, '+', "i", 2),
+      (1, '-', "d", 15),
+      (1, '+', "i", 15),
+      (1, '+', "i", 16),
+    ]);
+
+    let ids: HashSet<_> = anchors
+      .iter()
+      .map(|(hunk_id, _, class, lineno)| (hunk_id, class, lineno))
+      .collect();
+    assert_eq!(ids.len(), anchors.len());
+  }
+
+  /// The number of line numbers in the gutter and of lines in the content of
+  /// a rendered text file
+  fn gutter_and_content_lines(content: &str) -> (usize, usize) {
+    let mut out = Vec::new();
+    let lines = line_count(content.as_bytes()).unwrap();
+    render_lines(&mut out, content.as_bytes(), lines).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    (out.matches("<a href=\"#l").count(), out.matches("<span id=\"l").count())
+  }
+
+  #[test]
+  fn gutter_numbers_every_line_without_trailing_newline() {
+    assert_eq!(gutter_and_content_lines("one line"), (1, 1));
+    assert_eq!(gutter_and_content_lines("fn main() {\n}"), (2, 2));
+  }
+
+  #[test]
+  fn gutter_numbers_every_line_with_trailing_newline() {
+    assert_eq!(gutter_and_content_lines("one line\n"), (1, 1));
+    assert_eq!(gutter_and_content_lines("fn main() {\n}\n"), (2, 2));
+    assert_eq!(gutter_and_content_lines("trailing\n\n"), (2, 2));
+  }
+
+  #[test]
+  fn binary_heuristic_agrees_with_git() {
+    let temp = TempRepo::new("binary");
+    let odb = temp.repo.odb().unwrap();
+
+    let samples: &[&[u8]] = &[
+      b"",
+      b"plain text\n",
+      b"\xef\xbb\xbfwith a UTF-8 BOM\n",
+      b"\xff\xfewith a UTF-16 BOM",
+      b"\x00\x01\x02\xff\xfe\x00",
+      b"escape \x1b[1m sequences\x0c\n",
+      &[0x01; 100],
+      &[b'a'; 9000],
+    ];
+    for sample in samples {
+      let id = temp.repo.blob(sample).unwrap();
+      let blob = temp.repo.find_blob(id).unwrap();
+      assert_eq!(looks_binary(sample), blob.is_binary(), "{sample:?}");
+
+      // freshly written blobs are loose objects, which are streamed
+      let content = BlobContent::open(&temp.repo, &odb, id);
+      assert!(matches!(content, BlobContent::Loose { .. }));
+      assert_eq!(content.size(), sample.len());
+      assert_eq!(content.is_binary().unwrap(), blob.is_binary());
+
+      let mut read = Vec::new();
+      content.reader().read_to_end(&mut read).unwrap();
+      assert_eq!(read, *sample);
+    }
+  }
+
+  #[test]
+  fn submodule_commit_urls() {
+    let id = Oid::from_str("5e1111507cab4e1b09d11c4e6cac39f0d9784d80").unwrap();
+
+    assert_eq!(
+      hosted_commit_url("https://github.com/rust-lang/rust.git", id).as_deref(),
+      Some("https://github.com/rust-lang/rust/commit/5e1111507cab4e1b09d11c4e6cac39f0d9784d80"),
+    );
+    assert_eq!(
+      hosted_commit_url("git@gitlab.com:group/sub/project.git", id).as_deref(),
+      Some("https://gitlab.com/group/sub/project/-/commit/5e1111507cab4e1b09d11c4e6cac39f0d9784d80"),
+    );
+    assert_eq!(
+      hosted_commit_url("ssh://git@git.sr.ht/~user/repo", id).as_deref(),
+      Some("https://git.sr.ht/~user/repo/commit/5e1111507cab4e1b09d11c4e6cac39f0d9784d80"),
+    );
+    assert_eq!(hosted_commit_url("https://git.example.org/repo.git", id), None);
+    assert_eq!(hosted_commit_url("../sibling", id), None);
+  }
+
+  #[test]
+  fn line_count_ignores_trailing_newline() {
+    assert_eq!(line_count("a\nb".as_bytes()).unwrap(), 2);
+    assert_eq!(line_count("a\nb\n".as_bytes()).unwrap(), 2);
+    assert_eq!(line_count("a\r\nb\r\n".as_bytes()).unwrap(), 2);
+    assert_eq!(line_count("\n".as_bytes()).unwrap(), 1);
+  }
+
+  #[test]
+  fn tabs_are_expanded_to_the_next_tab_stop() {
+    assert_eq!(expand_tabs_to("\tx", 4), "    x");
+    assert_eq!(expand_tabs_to("ab\tx", 4), "ab  x");
+    assert_eq!(expand_tabs_to("abcd\tx", 4), "abcd    x");
+    assert_eq!(expand_tabs_to("a\t\tx", 4), "a       x");
+    assert_eq!(expand_tabs_to("a\tb\tc", 8), "a       b       c");
+    assert_eq!(expand_tabs_to("no tabs", 8), "no tabs");
+  }
+
+  #[test]
+  fn tab_stops_count_characters() {
+    // multibyte characters take a single column
+    assert_eq!(expand_tabs_to("caf\u{e9}\tx", 8), "caf\u{e9}    x");
+    assert_eq!(expand_tabs_to("\u{3bb}\u{3bb}\tx", 4), "\u{3bb}\u{3bb}  x");
+  }
+
+  #[test]
+  fn tab_size_follows_tab_mode() {
+    let style = TabSize.to_string();
+    match config::TAB_MODE {
+      TabMode::Css    => assert_eq!(style, format!(" style=\"tab-size: {};\"", config::TAB_WIDTH)),
+      TabMode::Expand => assert_eq!(style, ""),
+    }
+
+    let expanded = expand_tabs("a\tb");
+    match config::TAB_MODE {
+      TabMode::Css    => assert_eq!(expanded, "a\tb"),
+      TabMode::Expand => assert_eq!(expanded, expand_tabs_to("a\tb", config::TAB_WIDTH)),
+    }
+  }
+
+  #[test]
+  fn manifest_entries_round_trip() {
+    let path = "dir/\"quoted\" \\ caf\u{e9}\t.txt";
+    let line = format!("  \"{}\": \"abc123\",", JsonEscaped(path));
+
+    assert_eq!(
+      parse_manifest_entry(line.trim()),
+      Some((String::from(path), String::from("abc123"))),
+    );
+    assert_eq!(
+      parse_manifest_entry("\"a\\u003cb\": \"abc123\""),
+      Some((String::from("a<b"), String::from("abc123"))),
+    );
+    assert_eq!(parse_manifest_entry("\"unterminated: \"abc123\""), None);
+    assert_eq!(parse_manifest_entry("\"path\" \"abc123\""), None);
+  }
+
+  #[test]
+  fn internal_files_are_left_out_of_the_manifest() {
+    assert!(is_listed_in_manifest("yagit/tree/index.html"));
+    assert!(is_listed_in_manifest("feed.xml"));
+    assert!(!is_listed_in_manifest("manifest.json"));
+    assert!(!is_listed_in_manifest("yagit/.yagit-state"));
+    assert!(!is_listed_in_manifest("yagit/.blobs/5e1111507cab4e1b09d11c4e6cac39f0d9784d80"));
+  }
+}
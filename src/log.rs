@@ -1,10 +1,16 @@
 //! Macros for logging.
 //!
-//! This implementation is NOT thread safe, since yagit is only expected to run
-//! on my single-threaded server.
-#![allow(static_mut_refs)]
+//! Messages may be logged from the threads blob pages are rendered on, so the
+//! job counter is kept behind a lock. It should still only be updated from the
+//! main thread.
 
-use std::{io::{self, Write}, fmt::Arguments, time::Duration};
+use std::{
+  cell::Cell,
+  io::{self, Write},
+  fmt::{self, Arguments},
+  sync::{Mutex, MutexGuard, atomic::{AtomicBool, Ordering}},
+  time::{Duration, Instant},
+};
 
 const BOLD_RED:    &str = "\u{001b}[1;31m";
 const BOLD_GREEN:  &str = "\u{001b}[1;32m";
@@ -16,14 +22,19 @@ const UNDERLINE:   &str = "\u{001b}[4m";
 const RESET:       &str = "\u{001b}[0m";
 
 const PROGRAM_VERSION: &str = env!("CARGO_PKG_VERSION");
-static mut COUNTER: Counter = Counter {
+static COUNTER: Mutex<Counter> = Mutex::new(Counter {
   total: 0,
   count: 0,
   current_repo_name: String::new(),
-};
+  start:             None,
+  eta:               String::new(),
+});
+/// Whether only errors and warnings should be logged
+static QUIET: AtomicBool = AtomicBool::new(false);
 
+#[doc(hidden)]
 #[derive(Clone, Copy, Debug)]
-pub(crate) enum Level {
+pub enum Level {
   Error,
   Info,
   Warn,
@@ -34,9 +45,18 @@ struct Counter {
   total:             usize,
   count:             usize,
   current_repo_name: String,
+  /// The time at which the first job was started
+  start:             Option<Instant>,
+  /// The estimated remaining time, as displayed in the last progress message
+  eta:               String,
 }
 
-pub(crate) fn log(level: Level, args: &Arguments<'_>) {
+#[doc(hidden)]
+pub fn log(level: Level, args: &Arguments<'_>) {
+  if is_quiet() && matches!(level, Level::Info | Level::Usage) {
+    return;
+  }
+
   match level {
     Level::Error => {
       eprintln!("     {BOLD_RED}Error{RESET} {args}");
@@ -58,7 +78,8 @@ pub(crate) fn log(level: Level, args: &Arguments<'_>) {
   }
 }
 
-pub(crate) fn query(args: &Arguments<'_>) -> String {
+#[doc(hidden)]
+pub fn query(args: &Arguments<'_>) -> String {
   let mut stdout = io::stdout();
   let stdin = io::stdin();
   let mut result = String::new();
@@ -77,55 +98,106 @@ pub(crate) fn query(args: &Arguments<'_>) -> String {
   result
 }
 
+pub fn set_quiet(quiet: bool) {
+  QUIET.store(quiet, Ordering::Relaxed);
+}
+
+fn is_quiet() -> bool {
+  QUIET.load(Ordering::Relaxed)
+}
+
+/// Locks the job counter, which is never left in an inconsistent state: so
+/// it's fine to keep using it if a thread panicked while holding the lock
+fn counter() -> MutexGuard<'static, Counter> {
+  COUNTER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 pub fn set_job_count(total: usize) {
-  unsafe {
-    COUNTER.total = total;
-    COUNTER.count = 0;
-  }
+  let mut counter = counter();
+  counter.total = total;
+  counter.count = 0;
+  counter.start = Some(Instant::now());
+  counter.eta.clear();
 }
 
 /// Logs a message telling the user the system has started rendering a job
 pub fn render_start(repo_name: &str) {
-  unsafe {
-    COUNTER.count += 1;
-    COUNTER.current_repo_name.clear();
-    COUNTER.current_repo_name.push_str(repo_name);
-
-    log_current_job();
+  {
+    let mut counter = counter();
+    counter.count += 1;
+    counter.current_repo_name.clear();
+    counter.current_repo_name.push_str(repo_name);
   }
+
+  log_current_job();
 }
 
 /// Logs a message telling the user the system has finished rendering a job
 pub fn render_done() {
-  unsafe {
-    debug_assert!(COUNTER.count > 0);
-
-    let space_padding = "... [/]".len() + 2 * crate::log_floor(COUNTER.total);
-    println!(
-      "  {BOLD_GREEN}Rendered{RESET} {name}{empty:space_padding$}",
-      name  = COUNTER.current_repo_name,
-      empty = "",
-    );
+  if is_quiet() {
+    return;
   }
+
+  let counter = counter();
+  debug_assert!(counter.count > 0);
+
+  let space_padding = "... [/]".len()
+    + 2 * crate::log_floor(counter.total)
+    + counter.eta.len();
+  println!(
+    "  {BOLD_GREEN}Rendered{RESET} {name}{empty:space_padding$}",
+    name  = counter.current_repo_name,
+    empty = "",
+  );
 }
 
 fn log_current_job() {
-  unsafe {
-    if COUNTER.count == 0 {
-      return;
+  if is_quiet() {
+    return;
+  }
+
+  let mut counter = counter();
+  if counter.count == 0 {
+    return;
+  }
+
+  // estimate the remaining time from the average time per completed job
+  let done = counter.count - 1;
+  counter.eta = match counter.start {
+    Some(start) if done > 0 => {
+      let per_job = start.elapsed() / done as u32;
+      let remaining = per_job * counter.total.saturating_sub(done) as u32;
+      format!(" ETA {}", Eta(remaining))
     }
+    _ => String::new(),
+  };
 
-    let mut stdout = io::stdout();
+  let mut stdout = io::stdout();
 
-    let _ = write!(
-      stdout,
-      " {BOLD_CYAN}Rendering{RESET} {name}... {BOLD_WHITE}[{count:>padding$}/{total}]{RESET}\r",
-      count = COUNTER.count,
-      total = COUNTER.total,
-      padding = crate::log_floor(COUNTER.total),
-      name = COUNTER.current_repo_name,
-    );
-    let _ = stdout.flush();
+  let _ = write!(
+    stdout,
+    " {BOLD_CYAN}Rendering{RESET} {name}... {BOLD_WHITE}[{count:>padding$}/{total}]{RESET}{eta}\r",
+    count = counter.count,
+    total = counter.total,
+    padding = crate::log_floor(counter.total),
+    name = counter.current_repo_name,
+    eta = counter.eta,
+  );
+  let _ = stdout.flush();
+}
+
+/// Formats a duration as minutes and seconds, such as `2m05s`
+struct Eta(Duration);
+
+impl fmt::Display for Eta {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let secs = self.0.as_secs();
+
+    if secs >= 60 {
+      write!(f, "{}m{:02}s", secs / 60, secs % 60)
+    } else {
+      write!(f, "{secs}s")
+    }
   }
 }
 
@@ -181,12 +253,44 @@ macro_rules! query {
   });
 }
 
-pub fn finished(duration: Duration) {
+/// Counts of the work done over a run, reported by [`finished`]
+#[derive(Debug, Default)]
+pub struct Stats {
+  pub repos:           Cell<usize>,
+  pub commits_written: Cell<usize>,
+  pub commits_skipped: Cell<usize>,
+  pub blobs_written:   Cell<usize>,
+  pub blobs_skipped:   Cell<usize>,
+}
+
+impl Stats {
+  pub fn incr(counter: &Cell<usize>) {
+    counter.set(counter.get() + 1);
+  }
+}
+
+pub fn finished(duration: Duration, stats: &Stats) {
+  if is_quiet() {
+    return;
+  }
+
   let duration = duration.as_millis() / 100;
   let secs  = duration / 10;
   let dsecs = duration % 10;
 
   println!("  {BOLD_GREEN}Finished{RESET} Rendering took {secs}.{dsecs}s");
+
+  if stats.repos.get() > 0 {
+    println!(
+      "           {repos} {repos_label}, {cw} commit pages written ({cs} up to date), {bw} file pages written ({bs} up to date)",
+      repos       = stats.repos.get(),
+      repos_label = if stats.repos.get() == 1 { "repository" } else { "repositories" },
+      cw          = stats.commits_written.get(),
+      cs          = stats.commits_skipped.get(),
+      bw          = stats.blobs_written.get(),
+      bs          = stats.blobs_skipped.get(),
+    );
+  }
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -198,7 +302,16 @@ pub fn version(program_name: &str) {
   }
 }
 
-#[cfg(not(target_arch = "x86_64"))]
+#[cfg(target_arch = "aarch64")]
+pub fn version(program_name: &str) {
+  if std::arch::is_aarch64_feature_detected!("neon") {
+    infoln!("Running {BOLD_WHITE}{program_name} {PROGRAM_VERSION}{RESET} (SIMD optimizations enabled)");
+  } else {
+    infoln!("Running {BOLD_WHITE}{program_name} {PROGRAM_VERSION}{RESET}");
+  }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
 pub fn version(program_name: &str) {
   infoln!("Running {BOLD_WHITE}{program_name} {PROGRAM_VERSION}{RESET}");
 }
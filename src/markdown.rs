@@ -1,16 +1,55 @@
-use std::io::{self, Write};
-use crate::{BLOB_SUBDIR, Escaped};
-use pulldown_cmark::{Parser, Options, Event, Tag, TagEnd, LinkType};
+use std::{io::{self, Write}, collections::HashMap, cmp};
+use crate::{Escaped, EscapedUrl, config::{self, RawHtml}};
+use pulldown_cmark::{Parser, Options, Event, Tag, TagEnd, LinkType, HeadingLevel, CodeBlockKind};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-struct State {
+/// The placeholder for raw HTML when `config::RAW_HTML` is `RawHtml::Comment`
+const RAW_HTML_COMMENT: &str = "<!-- raw HTML omitted -->";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct State<'a> {
   in_non_writing_block: bool,
   in_table_head: bool,
+  // the number of headings with a given slug seen so far, used for
+  // disambiguating the ids of the headings
+  slugs: HashMap<String, usize>,
+  heading_offset: usize,
+  location: Location<'a>,
+  autolink: Option<Autolink<'a>>,
+  // text in links and code blocks is never autolinked
+  in_link: bool,
+  in_code_block: bool,
+}
+
+/// Where a document is found in a repository, used to resolve the relative
+/// URLs of its images
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location<'a> {
+  /// The URL of the raw blobs of the repository, such as `/yagit/blob`
+  pub blob_root: &'a str,
+  /// The directory of the document, relative to the root of the repository
+  pub dir:       &'a str,
 }
 
+/// Options for linking bare URLs and issue references, such as `#123`, in text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Autolink<'a> {
+  /// The URL of issues, where `{id}` stands for the number of the issue, or
+  /// `None` if issue references shouldn't be linked
+  pub issue_url: Option<&'a str>,
+}
+
+/// The level of every heading is increased by `heading_offset` (up to `<h6>`),
+/// which is useful when embedding the document in a page with its own `<h1>`.
+/// Bare URLs and issue references are linked if `autolink` is set.
 // Addapted from pulldown_cmark/html.rs
 // <https://github.com/pulldown-cmark/pulldown-cmark/>
-pub fn render_html<W: Write>(w: &mut W, src: &String) -> io::Result<()> {
+pub fn render_html<W: Write>(
+  w: &mut W,
+  src: &String,
+  heading_offset: usize,
+  location: Location<'_>,
+  autolink: Option<Autolink<'_>>,
+) -> io::Result<()> {
   let mut opt = Options::empty();
   opt.insert(Options::ENABLE_TABLES);
   opt.insert(Options::ENABLE_STRIKETHROUGH);
@@ -19,49 +58,147 @@ pub fn render_html<W: Write>(w: &mut W, src: &String) -> io::Result<()> {
   opt.insert(Options::ENABLE_DEFINITION_LIST);
   opt.insert(Options::ENABLE_SUPERSCRIPT);
   opt.insert(Options::ENABLE_SUBSCRIPT);
+  opt.insert(Options::ENABLE_MATH);
+  opt.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
 
   let mut p = Parser::new_ext(src.as_ref(), opt);
   let mut state = State {
     in_non_writing_block: false,
     in_table_head: true,
+    slugs: HashMap::new(),
+    heading_offset,
+    location,
+    autolink,
+    in_link: false,
+    in_code_block: false,
   };
 
   while let Some(event) = p.next() {
-    match event {
-      Event::Start(tag) => start_tag(w, tag, &mut state, &mut p)?,
-      Event::End(tag)   => end_tag(w, tag, &mut state)?,
-      Event::Text(text) => if !state.in_non_writing_block {
-        if text.ends_with('\n') {
-          write!(w, "{}", Escaped(&text))?;
-        } else {
-          writeln!(w, "{}", Escaped(&text))?;
-        }
-      },
-      Event::Code(text) => write!(w, "<code>{}</code>", Escaped(&text))?,
-      Event::InlineMath(_) => {
-        unreachable!("inline math is not supported");
-      }
-      Event::DisplayMath(_) => {
-        unreachable!("display math is not supported");
-      }
-      Event::SoftBreak => writeln!(w)?,
-      Event::HardBreak => writeln!(w, "<br />")?,
-      Event::Rule      => writeln!(w, "<hr />")?,
-      Event::TaskListMarker(true) => {
-        writeln!(w, "<input disabled=\"\" type=\"checkbox\" checked=\"\"/>")?;
-      }
-      Event::TaskListMarker(false) => {
-        writeln!(w, "<input disabled=\"\" type=\"checkbox\"/>")?;
+    render_event(w, event, &mut state, &mut p)?;
+  }
+  Ok(())
+}
+
+fn render_event<W: Write>(
+  w: &mut W,
+  event: Event<'_>,
+  state: &mut State,
+  p: &mut Parser,
+) -> io::Result<()> {
+  match event {
+    Event::Start(Tag::Heading { level, .. }) => heading(w, level, state, p)?,
+    Event::Start(tag) => start_tag(w, tag, state, p)?,
+    Event::End(tag)   => end_tag(w, tag, state)?,
+    Event::Text(text) => if !state.in_non_writing_block {
+      if state.in_link || state.in_code_block {
+        write!(w, "{}", Escaped(&text))?;
+      } else {
+        write_autolinked(w, &text, state.autolink)?;
       }
-      Event::Html(_) | Event::InlineHtml(_) => {} // running in safe mode
-      Event::FootnoteReference(_) => {
-        unreachable!("footnotes are not supported");
+    },
+    Event::Code(text) => write!(w, "<code>{}</code>", Escaped(&text))?,
+    Event::InlineMath(math) => if config::MATHJAX {
+      write!(w, "<span class=\"math\">\\({}\\)</span>", Escaped(&math))?;
+    } else {
+      write!(w, "<code class=\"math\">{}</code>", Escaped(&math))?;
+    },
+    Event::DisplayMath(math) => if config::MATHJAX {
+      writeln!(w, "<div class=\"math\">\\[{}\\]</div>", Escaped(&math))?;
+    } else {
+      writeln!(w, "<pre class=\"math\">{}</pre>", Escaped(&math))?;
+    },
+    Event::SoftBreak => writeln!(w)?,
+    Event::HardBreak => writeln!(w, "<br />")?,
+    Event::Rule      => writeln!(w, "<hr />")?,
+    Event::TaskListMarker(true) => {
+      writeln!(w, "<input disabled=\"\" type=\"checkbox\" checked=\"\"/>")?;
+    }
+    Event::TaskListMarker(false) => {
+      writeln!(w, "<input disabled=\"\" type=\"checkbox\"/>")?;
+    }
+    // running in safe mode: raw HTML is never emitted
+    Event::Html(html) => if config::RAW_HTML == RawHtml::Escape {
+      write!(w, "{}", Escaped(&html))?;
+    },
+    Event::InlineHtml(html) => match config::RAW_HTML {
+      RawHtml::Drop    => {}
+      RawHtml::Escape  => {
+        write!(w, "<code class=\"raw-html\">{}</code>", Escaped(&html))?;
       }
+      RawHtml::Comment => write!(w, "{RAW_HTML_COMMENT}")?,
+    },
+    Event::FootnoteReference(_) => {
+      unreachable!("footnotes are not supported");
     }
   }
+
+  Ok(())
+}
+
+/// Renders a heading with an id, so that it can be linked to
+///
+/// The id is derived from the text of the heading, so the contents of the
+/// heading have to be buffered before the opening tag is written.
+fn heading<W: Write>(
+  w: &mut W,
+  level: HeadingLevel,
+  state: &mut State,
+  p: &mut Parser,
+) -> io::Result<()> {
+  let mut content = Vec::new();
+  let mut text = String::new();
+
+  while let Some(event) = p.next() {
+    match event {
+      Event::End(TagEnd::Heading(_)) => break,
+      Event::Text(ref t) | Event::Code(ref t) => text.push_str(t),
+      _ => {}
+    }
+
+    render_event(&mut content, event, state, p)?;
+  }
+
+  let level = HeadingLevel::try_from(cmp::min(level as usize + state.heading_offset, 6))
+    .expect("heading level should be between 1 and 6");
+
+  let mut slug = slugify(&text);
+  let count = state.slugs.entry(slug.clone()).or_insert(0);
+  if *count > 0 {
+    slug = format!("{slug}-{count}");
+  }
+  *count += 1;
+
+  write!(w, "<{level} id=\"{slug}\">", slug = Escaped(&slug))?;
+  write!(w, "<a href=\"#{slug}\" class=\"anchor\" aria-hidden=\"true\">#</a>",
+            slug = Escaped(&slug))?;
+  w.write_all(&content)?;
+  writeln!(w, "</{level}>")?;
+
   Ok(())
 }
 
+/// Converts the text of a heading to an id: e.g. "Getting Started!" becomes
+/// "getting-started"
+fn slugify(text: &str) -> String {
+  let slug: String = text
+    .trim()
+    .chars()
+    .filter_map(|c| match c {
+      ' '                      => Some('-'),
+      '-' | '_'                => Some(c),
+      c if c.is_alphanumeric() => Some(c),
+      _                        => None,
+    })
+    .flat_map(char::to_lowercase)
+    .collect();
+
+  if slug.is_empty() {
+    String::from("section")
+  } else {
+    slug
+  }
+}
+
 // Addapted from pulldown_cmark/html.rs
 // <https://github.com/pulldown-cmark/pulldown-cmark/>
 /// Returns `Ok(t)` if successful,
@@ -76,9 +213,15 @@ fn start_tag<W: Write>(
     Tag::HtmlBlock => {
       // runing in safe mode
       state.in_non_writing_block = true;
+
+      match config::RAW_HTML {
+        RawHtml::Drop    => {}
+        RawHtml::Escape  => write!(w, "<pre class=\"raw-html\"><code>")?,
+        RawHtml::Comment => writeln!(w, "{RAW_HTML_COMMENT}")?,
+      }
     }
     Tag::Paragraph             => writeln!(w, "<p>")?,
-    Tag::Heading { level, .. } => write!(w, "<{level}>")?,
+    Tag::Heading { .. } => unreachable!("headings are handled by heading()"),
     Tag::Subscript             => write!(w, "<sub>")?,
     Tag::Superscript           => write!(w, "<sup>")?,
     Tag::Table(_alignments)    => write!(w, "<table>")?,
@@ -92,9 +235,25 @@ fn start_tag<W: Write>(
     } else {
       write!(w, "<td>")?;
     },
-    Tag::CodeBlock(_) => {
+    Tag::CodeBlock(kind) => {
+      state.in_code_block = true;
       writeln!(w, "<div class=\"code-block\">")?;
       write!(w, "<pre>")?;
+
+      // the info string may contain other attributes after the language,
+      // e.g. "rust,ignore"
+      let lang = match kind {
+        CodeBlockKind::Fenced(ref info) => {
+          info.split([' ', '\t', ',']).next().unwrap_or_default()
+        }
+        CodeBlockKind::Indented => "",
+      };
+
+      if lang.is_empty() {
+        write!(w, "<code>")?;
+      } else {
+        write!(w, "<code class=\"language-{lang}\">", lang = Escaped(lang))?;
+      }
     }
     Tag::BlockQuote(_)            => writeln!(w, "<blockquote>")?,
     Tag::List(Some(1))            => writeln!(w, "<ol>")?,
@@ -108,18 +267,19 @@ fn start_tag<W: Write>(
     Tag::Strong                   => write!(w, "<strong>")?,
     Tag::Strikethrough            => write!(w, "<del>")?,
     Tag::Link { link_type: LinkType::Email, dest_url, .. } => {
-      write!(w, "<a href=\"mailto:{url}\">", url = Escaped(&dest_url))?;
+      state.in_link = true;
+      write!(w, "<a href=\"mailto:{url}\">", url = EscapedUrl(&dest_url))?;
     }
     Tag::Link { dest_url, .. } => {
-      write!(w, "<a href=\"{url}\">", url = Escaped(&dest_url))?;
+      state.in_link = true;
+      write!(w, "<a href=\"{url}\">", url = EscapedUrl(&dest_url))?;
     }
     Tag::Image { dest_url, title, .. } => {
-      if dest_url.starts_with("https://") || dest_url.starts_with("http://") {
-        write!(w, "<img src=\"{url}\" ", url = Escaped(&dest_url))?;
+      if is_absolute_url(&dest_url) {
+        write!(w, "<img src=\"{url}\" ", url = EscapedUrl(&dest_url))?;
       } else {
-        // relative URL
-        write!(w, "<img src=\"./{BLOB_SUBDIR}/{url}\" ",
-                  url = Escaped(&dest_url))?;
+        let url = resolve_relative_url(state.location, &dest_url);
+        write!(w, "<img src=\"{url}\" ", url = EscapedUrl(&url))?;
       };
 
       if let Some(Event::Text(alt)) = p.next() {
@@ -136,13 +296,158 @@ fn start_tag<W: Write>(
       unreachable!("footnotes are not supported");
     }
     Tag::MetadataBlock(_) => {
-      unreachable!("metadata blocks are not supported");
+      // front matter is meant for other tools, not for display
+      state.in_non_writing_block = true;
     }
   }
 
   Ok(())
 }
 
+/// Writes `text` escaped as HTML, linking the bare URLs and issue references
+/// in it if `autolink` is set
+pub fn write_autolinked<W: Write>(
+  w: &mut W,
+  text: &str,
+  autolink: Option<Autolink<'_>>,
+) -> io::Result<()> {
+  let Some(autolink) = autolink else {
+    return write!(w, "{}", Escaped(text));
+  };
+
+  let mut mark = 0;
+  let mut i = 0;
+  while let Some(c) = text[i..].chars().next() {
+    // only link whole words: e.g. not the "#1" in "a#1"
+    let at_word_start = text[..i]
+      .chars()
+      .next_back()
+      .is_none_or(|prev| !prev.is_alphanumeric() && prev != '&');
+
+    if at_word_start {
+      if let Some(len) = url_len(&text[i..]) {
+        let url = &text[i..i+len];
+        write!(w, "{}", Escaped(&text[mark..i]))?;
+        write!(w, "<a href=\"{}\">{}</a>", EscapedUrl(url), Escaped(url))?;
+        i += len;
+        mark = i;
+        continue;
+      }
+
+      if let (Some(issue_url), Some(len)) = (autolink.issue_url, issue_ref_len(&text[i..])) {
+        let id = &text[i+1..i+len];
+        let url = issue_url.replace("{id}", id);
+        write!(w, "{}", Escaped(&text[mark..i]))?;
+        write!(w, "<a href=\"{}\">#{id}</a>", EscapedUrl(&url))?;
+        i += len;
+        mark = i;
+        continue;
+      }
+    }
+
+    i += c.len_utf8();
+  }
+
+  write!(w, "{}", Escaped(&text[mark..]))
+}
+
+/// The length of the `http://` or `https://` URL at the start of `text`, if
+/// any
+///
+/// Punctuation at the end of the URL is assumed to be part of the surrounding
+/// sentence, except for closing parentheses which have a matching opening
+/// parenthesis in the URL, as in Wikipedia links.
+fn url_len(text: &str) -> Option<usize> {
+  let scheme_len = if text.starts_with("https://") {
+    "https://".len()
+  } else if text.starts_with("http://") {
+    "http://".len()
+  } else {
+    return None;
+  };
+
+  let end = text
+    .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+    .unwrap_or(text.len());
+  let mut url = &text[..end];
+
+  loop {
+    match url.chars().next_back() {
+      Some('.' | ',' | ':' | ';' | '!' | '?' | '\'' | '"') => {}
+      Some(')') if url.matches('(').count() < url.matches(')').count() => {}
+      _ => break,
+    }
+    url = &url[..url.len()-1];
+  }
+
+  if url.len() > scheme_len {
+    Some(url.len())
+  } else {
+    None
+  }
+}
+
+/// The length of the issue reference at the start of `text`, such as `#123`,
+/// if any
+fn issue_ref_len(text: &str) -> Option<usize> {
+  let digits = text.strip_prefix('#')?;
+  let n = digits.bytes().take_while(u8::is_ascii_digit).count();
+  let followed_by_word = digits[n..]
+    .chars()
+    .next()
+    .is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+  if n > 0 && !followed_by_word {
+    Some(1 + n)
+  } else {
+    None
+  }
+}
+
+/// Checks if `url` has a scheme, such as `https:` or `data:`, or is a
+/// protocol-relative URL
+fn is_absolute_url(url: &str) -> bool {
+  if url.starts_with("//") {
+    return true;
+  }
+
+  match url.split_once(':') {
+    Some((scheme, _)) => {
+      scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    }
+    None => false,
+  }
+}
+
+/// Resolves a relative URL in a document to the URL of the raw blob it points
+/// to, e.g. `../logo.png` in `docs/README.md` becomes `{blob_root}/logo.png`
+///
+/// As in GitHub, URLs starting with `/` are relative to the root of the
+/// repository. `..` never goes above the root of the repository.
+fn resolve_relative_url(location: Location<'_>, url: &str) -> String {
+  let (dir, url) = match url.strip_prefix('/') {
+    Some(url) => ("", url),
+    None      => (location.dir, url),
+  };
+
+  let mut segments: Vec<&str> = Vec::new();
+  for segment in dir.split('/').chain(url.split('/')) {
+    match segment {
+      "" | "." => {}
+      ".."     => { segments.pop(); }
+      segment  => segments.push(segment),
+    }
+  }
+
+  let mut result = String::from(location.blob_root.trim_end_matches('/'));
+  for segment in segments {
+    result.push('/');
+    result.push_str(segment);
+  }
+  result
+}
+
 // Addapted from pulldown_cmark/html.rs
 /// Returns `Ok(t)` if successful,
 /// where `t` indicates whether or not we are in a non-writting block
@@ -155,9 +460,13 @@ fn end_tag<W: Write>(
     TagEnd::HtmlBlock => {
       // runing in safe mode
       state.in_non_writing_block = false;
+
+      if config::RAW_HTML == RawHtml::Escape {
+        writeln!(w, "</code></pre>")?;
+      }
     }
     TagEnd::Paragraph      => writeln!(w, "</p>")?,
-    TagEnd::Heading(level) => writeln!(w, "</{level}>")?,
+    TagEnd::Heading(_)     => unreachable!("headings are handled by heading()"),
     TagEnd::Subscript      => write!(w, "</sub>")?,
     TagEnd::Superscript    => write!(w, "</sup>")?,
     TagEnd::Table => {
@@ -177,7 +486,8 @@ fn end_tag<W: Write>(
       write!(w, "</td>")?;
     },
     TagEnd::CodeBlock => {
-      writeln!(w, "</pre>")?;
+      state.in_code_block = false;
+      writeln!(w, "</code></pre>")?;
       writeln!(w, "</div>")?;
     }
     TagEnd::BlockQuote(_)            => writeln!(w, "</blockquote>")?,
@@ -190,16 +500,108 @@ fn end_tag<W: Write>(
     TagEnd::Emphasis                 => write!(w, "</em>")?,
     TagEnd::Strong                   => write!(w, "</strong>")?,
     TagEnd::Strikethrough            => write!(w, "</del>")?,
-    TagEnd::Link                     => write!(w, "</a>")?,
+    TagEnd::Link => {
+      state.in_link = false;
+      write!(w, "</a>")?;
+    }
     TagEnd::Image                    => {} // handled in start_tag
     TagEnd::FootnoteDefinition => {
       unreachable!("footnotes are not supported");
     }
     TagEnd::MetadataBlock(_) => {
-      unreachable!("metadata blocks are not supported");
+      state.in_non_writing_block = false;
     }
   }
 
   Ok(())
 }
 
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const LOCATION: Location<'static> = Location {
+    blob_root: "/repo/blob",
+    dir:       "docs",
+  };
+
+  fn render(src: &str) -> String {
+    let mut out = Vec::new();
+    render_html(&mut out, &src.to_string(), 0, LOCATION, None).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  fn autolinked(text: &str) -> String {
+    let autolink = Autolink { issue_url: Some("https://example.com/issues/{id}") };
+    let mut out = Vec::new();
+    write_autolinked(&mut out, text, Some(autolink)).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn autolinks_urls_without_trailing_punctuation() {
+    assert_eq!(
+      autolinked("See https://example.com/a?b=1&c=2. Or (http://x.org/Foo_(bar))!"),
+      "See <a href=\"https://example.com/a?b=1&amp;c=2\">https://example.com/a?b=1&amp;c=2</a>. \
+       Or (<a href=\"http://x.org/Foo_(bar)\">http://x.org/Foo_(bar)</a>)!",
+    );
+    assert_eq!(autolinked("https:// alone"), "https:// alone");
+  }
+
+  #[test]
+  fn autolinks_issue_references() {
+    assert_eq!(
+      autolinked("Fixes #12, but not a#3, #4b or &#38;."),
+      "Fixes <a href=\"https://example.com/issues/12\">#12</a>, but not a#3, #4b or &amp;#38;.",
+    );
+  }
+
+  /// Compares the rendering of every `.md` file in `tests/markdown` to the
+  /// `.html` file of the same name
+  ///
+  /// Run with `YAGIT_BLESS=1` to overwrite the expected outputs with the
+  /// current ones after an intended change in behavior.
+  #[test]
+  fn golden_files() {
+    let dir = std::path::Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/markdown"));
+    let bless = std::env::var_os("YAGIT_BLESS").is_some();
+
+    let mut inputs: Vec<_> = std::fs::read_dir(dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().path())
+      .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+      .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty());
+
+    let mut mismatches = Vec::new();
+    for input in inputs {
+      let src = std::fs::read_to_string(&input).unwrap();
+      let output = render(&src);
+      let golden = input.with_extension("html");
+
+      if bless {
+        std::fs::write(&golden, &output).unwrap();
+        continue;
+      }
+
+      let expected = std::fs::read_to_string(&golden)
+        .unwrap_or_else(|e| panic!("Could not read {golden:?}: {e}"));
+      if output != expected {
+        eprintln!("{input:?} rendered to:\n{output}");
+        mismatches.push(input);
+      }
+    }
+
+    assert!(mismatches.is_empty(), "unexpected rendering of {mismatches:?}");
+  }
+
+  #[test]
+  fn paragraph_with_inline_code_and_emphasis() {
+    assert_eq!(
+      render("Some `inline code` and *emphasis*, all\nin one paragraph.\n"),
+      "<p>\nSome <code>inline code</code> and <em>emphasis</em>, all\nin one paragraph.</p>\n",
+    );
+  }
+}
@@ -0,0 +1,124 @@
+//! Per-repository configuration overrides
+//!
+//! Repositories may override some of the options in the global `config.toml`
+//! with a `.yagit.toml` file at the root of their `HEAD`: options absent from
+//! this file fall back to the global configuration.
+//!
+//! The file is read from `HEAD` even when rendering another branch with
+//! `--branch`: the description and the clone URL are those of the repository,
+//! shared by the index of projects and the pages of all branches.
+//!
+//! There is no override for whether to show blame: yagit doesn't render blame
+//! pages, so there is nothing to toggle.
+
+use git2::Repository;
+use toml::{Table, Value};
+
+/// The path of the configuration file, relative to the root of the repository
+pub const REPO_CONFIG_PATH: &str = ".yagit.toml";
+
+#[derive(Clone, Debug, Default)]
+pub struct RepoConfig {
+  pub description: Option<String>,
+  /// Overrides `git.clone_url`
+  pub clone_url:   Option<String>,
+}
+
+impl RepoConfig {
+  /// Reads the configuration file at `HEAD` of `repo`
+  ///
+  /// Returns the default configuration (which overrides nothing) if there is
+  /// no such file, or if it is invalid.
+  pub fn read(repo: &Repository, name: &str) -> Self {
+    let Some(content) = read_blob(repo) else {
+      return Self::default();
+    };
+
+    let table = match content.parse::<Table>() {
+      Ok(table) => table,
+      Err(e) => {
+        warnln!("Could not parse {REPO_CONFIG_PATH} in {name:?}: {e}. Ignoring it",
+                e = e.message());
+        return Self::default();
+      }
+    };
+
+    let mut config = Self::default();
+    for (key, value) in table {
+      match (key.as_str(), value) {
+        ("description", Value::String(s)) => config.description = Some(s),
+        ("clone_url",   Value::String(s)) if is_clone_url(&s) => config.clone_url = Some(s),
+        ("clone_url",   Value::String(s)) => {
+          warnln!("clone_url in {REPO_CONFIG_PATH} of {name:?} is not an http(s), git or ssh URL: {s:?}. Ignoring it");
+        }
+        ("description" | "clone_url", _) => {
+          warnln!("{key:?} in {REPO_CONFIG_PATH} of {name:?} should be a string. Ignoring it");
+        }
+        ("blame", _) => {
+          warnln!("yagit has no blame view: ignoring {key:?} in {REPO_CONFIG_PATH} of {name:?}");
+        }
+        _ => {
+          warnln!("Unknown key {key:?} in {REPO_CONFIG_PATH} of {name:?}. Ignoring it");
+        }
+      }
+    }
+
+    config
+  }
+}
+
+/// The schemes of the clone URLs which are rendered as links
+pub const LINKED_SCHEMES: &[&str] = &["https://", "http://", "git://", "ssh://"];
+
+/// Checks if `url` is either an URL with one of [`LINKED_SCHEMES`] or an
+/// scp-like `user@host:path`
+pub fn is_clone_url(url: &str) -> bool {
+  if LINKED_SCHEMES.iter().any(|scheme| url.starts_with(scheme)) {
+    return true;
+  }
+
+  let Some((user_host, _)) = url.split_once(':') else {
+    return false;
+  };
+  match user_host.split_once('@') {
+    Some((user, host)) => {
+      !user.is_empty() && !host.is_empty()
+        && !user_host.contains(|c: char| c == '/' || c.is_whitespace())
+    }
+    None => false,
+  }
+}
+
+fn read_blob(repo: &Repository) -> Option<String> {
+  let tree = repo.head().ok()?.peel_to_tree().ok()?;
+  let blob = tree
+    .get_path(REPO_CONFIG_PATH.as_ref())
+    .ok()?
+    .to_object(repo)
+    .ok()?
+    .peel_to_blob()
+    .ok()?;
+
+  String::from_utf8(blob.content().to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn clone_urls() {
+    assert!(is_clone_url("https://example.org/repo.git"));
+    assert!(is_clone_url("http://example.org/repo.git"));
+    assert!(is_clone_url("git://example.org/repo"));
+    assert!(is_clone_url("ssh://git@example.org/repo.git"));
+    assert!(is_clone_url("git@example.org:repo.git"));
+
+    assert!(!is_clone_url("javascript:alert(1)"));
+    assert!(!is_clone_url("JavaScript://example.org/%0aalert(1)"));
+    assert!(!is_clone_url("data:text/html,<script>alert(1)</script>"));
+    assert!(!is_clone_url("@example.org:repo.git"));
+    assert!(!is_clone_url("git@:repo.git"));
+    assert!(!is_clone_url("/srv/git/repo.git"));
+  }
+}
@@ -0,0 +1,371 @@
+//! Parsing of commit signatures
+//!
+//! We do *not* verify signatures: we only extract the key which was used to
+//! sign a commit, as embedded in the signature itself.
+
+use std::fmt::{self, Display};
+
+const PGP_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+const PGP_FOOTER: &str = "-----END PGP SIGNATURE-----";
+const SSH_HEADER: &str = "-----BEGIN SSH SIGNATURE-----";
+const SSH_FOOTER: &str = "-----END SSH SIGNATURE-----";
+
+// OpenPGP constants: see RFC 9580
+const PGP_SIGNATURE_PACKET:   u8 = 2;
+const PGP_ISSUER_KEY_ID:      u8 = 16;
+const PGP_ISSUER_FINGERPRINT: u8 = 33;
+
+const SSHSIG_MAGIC: &[u8] = b"SSHSIG";
+
+const BASE64_ALPHABET: &[u8; 64] =
+  b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Signer {
+  /// An OpenPGP key, identified by its fingerprint or its key ID
+  Gpg { key_id: String },
+  /// An SSH public key, in the OpenSSH format
+  Ssh { key: String },
+  /// A signature whose format we don't understand, e.g. X.509 signatures
+  Unknown,
+}
+
+impl Signer {
+  /// Extracts the signing key from an ASCII-armored signature
+  pub fn parse(signature: &str) -> Self {
+    let signer = if let Some(body) = armored_body(signature, PGP_HEADER, PGP_FOOTER) {
+      pgp_key_id(&body).map(|key_id| Self::Gpg { key_id })
+    } else if let Some(body) = armored_body(signature, SSH_HEADER, SSH_FOOTER) {
+      ssh_key(&body).map(|key| Self::Ssh { key })
+    } else {
+      None
+    };
+
+    signer.unwrap_or(Self::Unknown)
+  }
+}
+
+impl Display for Signer {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Gpg { key_id } => write!(f, "GPG key {key_id}"),
+      Self::Ssh { key }    => write!(f, "SSH key {key}"),
+      Self::Unknown        => write!(f, "unknown key"),
+    }
+  }
+}
+
+/// Returns the decoded base64 content of an ASCII-armored block
+fn armored_body(s: &str, header: &str, footer: &str) -> Option<Vec<u8>> {
+  let start = s.find(header)? + header.len();
+  let end = start + s[start..].find(footer)?;
+  let mut body = &s[start..end];
+
+  // skip the armor headers (e.g. "Version: ..."), which are separated from
+  // the body by an empty line
+  if let Some(i) = body.find("\n\n") {
+    if body[..i].lines().skip(1).all(|l| l.contains(": ")) {
+      body = &body[i..];
+    }
+  }
+
+  // the CRC24 checksum of PGP armor starts with '='
+  let body = body
+    .lines()
+    .map(str::trim)
+    .take_while(|l| !l.starts_with('='))
+    .collect::<String>();
+
+  base64_decode(&body)
+}
+
+/// Extracts the issuer of an OpenPGP signature packet
+fn pgp_key_id(bytes: &[u8]) -> Option<String> {
+  let (tag, packet) = pgp_packet(bytes)?;
+  if tag != PGP_SIGNATURE_PACKET {
+    return None;
+  }
+
+  let version = *packet.first()?;
+  let mut i: usize;
+  let hashed_len;
+  match version {
+    4 => {
+      hashed_len = u16::from_be_bytes(packet.get(4..6)?.try_into().ok()?) as usize;
+      i = 6;
+    }
+    6 => {
+      hashed_len = u32::from_be_bytes(packet.get(4..8)?.try_into().ok()?) as usize;
+      i = 8;
+    }
+    _ => return None,
+  }
+
+  let hashed = packet.get(i..i.checked_add(hashed_len)?)?;
+  i += hashed_len;
+
+  let unhashed = if version == 4 {
+    let len = u16::from_be_bytes(packet.get(i..i + 2)?.try_into().ok()?) as usize;
+    packet.get(i + 2..(i + 2).checked_add(len)?)?
+  } else {
+    let len = u32::from_be_bytes(packet.get(i..i + 4)?.try_into().ok()?) as usize;
+    packet.get(i + 4..(i + 4).checked_add(len)?)?
+  };
+
+  // prefer the full fingerprint to the (ambiguous) 8-byte key ID
+  let mut key_id = None;
+  for subpackets in [hashed, unhashed] {
+    let mut subpackets = subpackets;
+    while let Some((kind, data, rest)) = pgp_subpacket(subpackets) {
+      match kind {
+        PGP_ISSUER_FINGERPRINT if data.len() > 1 => {
+          return Some(hex(&data[1..])); // skip the key version
+        }
+        PGP_ISSUER_KEY_ID if key_id.is_none() => {
+          key_id = Some(hex(data));
+        }
+        _ => {}
+      }
+      subpackets = rest;
+    }
+  }
+
+  key_id
+}
+
+/// Returns the tag and the body of the first packet in `bytes`
+fn pgp_packet(bytes: &[u8]) -> Option<(u8, &[u8])> {
+  let header = *bytes.first()?;
+  if header & 0x80 == 0 {
+    return None;
+  }
+
+  let (tag, len, offset) = if header & 0x40 != 0 {
+    // new packet format
+    let tag = header & 0x3f;
+    match *bytes.get(1)? as usize {
+      len @ 0..192   => (tag, len, 2),
+      len @ 192..224 => {
+        let len = ((len - 192) << 8) + *bytes.get(2)? as usize + 192;
+        (tag, len, 3)
+      }
+      255 => {
+        let len = u32::from_be_bytes(bytes.get(2..6)?.try_into().ok()?);
+        (tag, len as usize, 6)
+      }
+      _ => return None, // partial body lengths aren't used for signatures
+    }
+  } else {
+    // old packet format
+    let tag = (header >> 2) & 0x0f;
+    match header & 0x03 {
+      0 => (tag, *bytes.get(1)? as usize, 2),
+      1 => {
+        let len = u16::from_be_bytes(bytes.get(1..3)?.try_into().ok()?);
+        (tag, len as usize, 3)
+      }
+      2 => {
+        let len = u32::from_be_bytes(bytes.get(1..5)?.try_into().ok()?);
+        (tag, len as usize, 5)
+      }
+      _ => (tag, bytes.len() - 1, 1),
+    }
+  };
+
+  // lengths are untrusted, and may overflow on 32-bit targets
+  Some((tag, bytes.get(offset..len.checked_add(offset)?)?))
+}
+
+/// Returns the type and the data of the first subpacket in `bytes`, as well as
+/// the remaining subpackets
+fn pgp_subpacket(bytes: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+  let (len, offset) = match *bytes.first()? as usize {
+    len @ 0..192   => (len, 1),
+    len @ 192..255 => (((len - 192) << 8) + *bytes.get(1)? as usize + 192, 2),
+    _ => {
+      let len = u32::from_be_bytes(bytes.get(1..5)?.try_into().ok()?);
+      (len as usize, 5)
+    }
+  };
+
+  if len == 0 {
+    return None;
+  }
+
+  let subpacket = bytes.get(offset..len.checked_add(offset)?)?;
+  let kind = subpacket[0] & 0x7f; // the high bit is the "critical" flag
+
+  Some((kind, &subpacket[1..], &bytes[offset + len..]))
+}
+
+/// Extracts the public key of an SSHSIG signature blob
+fn ssh_key(bytes: &[u8]) -> Option<String> {
+  let rest = bytes.strip_prefix(SSHSIG_MAGIC)?;
+  let rest = rest.get(4..)?; // skip the version
+
+  let key_len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+  let key = rest.get(4..key_len.checked_add(4)?)?;
+
+  let type_len = u32::from_be_bytes(key.get(..4)?.try_into().ok()?) as usize;
+  let key_type = std::str::from_utf8(key.get(4..type_len.checked_add(4)?)?).ok()?;
+
+  Some(format!("{key_type} {}", base64_encode(key)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{b:02X}")).collect()
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+  let mut result = Vec::with_capacity(s.len() / 4 * 3);
+  let mut buff: u32 = 0;
+  let mut bits = 0;
+
+  for c in s.bytes().take_while(|&c| c != b'=') {
+    let value = BASE64_ALPHABET.iter().position(|&b| b == c)? as u32;
+    buff = (buff << 6) | value;
+    bits += 6;
+
+    if bits >= 8 {
+      bits -= 8;
+      result.push((buff >> bits) as u8);
+    }
+  }
+
+  Some(result)
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+  let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+  for chunk in bytes.chunks(3) {
+    let buff = chunk
+      .iter()
+      .enumerate()
+      .fold(0u32, |buff, (i, &b)| buff | (b as u32) << (16 - 8 * i));
+
+    for i in 0..4 {
+      if i <= chunk.len() {
+        let value = (buff >> (18 - 6 * i)) & 0x3f;
+        result.push(BASE64_ALPHABET[value as usize] as char);
+      } else {
+        result.push('=');
+      }
+    }
+  }
+
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PGP_SIGNATURE: &str = "\
+-----BEGIN PGP SIGNATURE-----
+
+iHUEABYIAB0WIQSBZp+OcJ+unKUm8URTgl1oNZGvwwUCatCJbAAKCRBTgl1oNZGv
+w5rjAQD4n7WLe3847TTbHK8CButE1Wis1B2W1xjNcd5/oDlEhwD7B1i/V1HEoz98
+ylRW8mrCKV6Fdc/rjbIePaUOaQcAng8=
+=GUPG
+-----END PGP SIGNATURE-----
+";
+
+  const SSH_SIGNATURE: &str = "\
+-----BEGIN SSH SIGNATURE-----
+U1NIU0lHAAAAAQAAADMAAAALc3NoLWVkMjU1MTkAAAAgwA4ou0y0PG6hcn8kuaiyFUj38K
+mNIbbite5qPCJXH5MAAAADZ2l0AAAAAAAAAAZzaGE1MTIAAABTAAAAC3NzaC1lZDI1NTE5
+AAAAQG4BAVLR2/EdmhWnkl3XmbHTY/YjSVPjcx2WcpZbQAcEw9jz+D8DrBSWYLW6J5EQzm
+lAzhHG6W9SM4GZ1z4qVQ4=
+-----END SSH SIGNATURE-----
+";
+
+  /// A v4 signature packet whose only subpacket is the (unhashed) issuer key
+  /// ID, as written by older versions of GnuPG
+  fn key_id_only_signature() -> String {
+    let mut packet = vec![
+      4,    // version
+      0x00, // signature type
+      22,   // public-key algorithm
+      8,    // hash algorithm
+      0, 0, // no hashed subpackets
+      0, 10, 9, PGP_ISSUER_KEY_ID,
+      0x53, 0x82, 0x5D, 0x68, 0x35, 0x91, 0xAF, 0xC3,
+      0xAB, 0xCD, // the left 16 bits of the hash
+    ];
+    packet.extend([0; 4]); // the signature itself, which we don't read
+    packet.splice(0..0, [0xC0 | PGP_SIGNATURE_PACKET, packet.len() as u8]);
+
+    format!("{PGP_HEADER}\nVersion: GnuPG v1\n\n{}\n{PGP_FOOTER}\n", base64_encode(&packet))
+  }
+
+  #[test]
+  fn pgp_issuer_fingerprint() {
+    assert_eq!(
+      Signer::parse(PGP_SIGNATURE),
+      Signer::Gpg { key_id: String::from("81669F8E709FAE9CA526F14453825D683591AFC3") },
+    );
+  }
+
+  #[test]
+  fn pgp_issuer_key_id() {
+    assert_eq!(
+      Signer::parse(&key_id_only_signature()),
+      Signer::Gpg { key_id: String::from("53825D683591AFC3") },
+    );
+  }
+
+  #[test]
+  fn ssh_public_key() {
+    assert_eq!(
+      Signer::parse(SSH_SIGNATURE),
+      Signer::Ssh {
+        key: String::from("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIMAOKLtMtDxuoXJ/JLmoshVI9/CpjSG24rXuajwiVx+T"),
+      },
+    );
+  }
+
+  #[test]
+  fn malformed_signatures() {
+    assert_eq!(Signer::parse(""), Signer::Unknown);
+    assert_eq!(Signer::parse("-----BEGIN SIGNED MESSAGE-----"), Signer::Unknown);
+    assert_eq!(Signer::parse(&format!("{PGP_HEADER}\niHUE")), Signer::Unknown);
+    assert_eq!(Signer::parse(&format!("{PGP_HEADER}\n!!!!\n{PGP_FOOTER}")), Signer::Unknown);
+    assert_eq!(Signer::parse(&format!("{SSH_HEADER}\nU1NIU0lH\n{SSH_FOOTER}")), Signer::Unknown);
+  }
+
+  #[test]
+  fn truncated_signatures_dont_panic() {
+    for signature in [PGP_SIGNATURE, SSH_SIGNATURE, &key_id_only_signature()] {
+      for (i, _) in signature.char_indices() {
+        let _ = Signer::parse(&signature[..i]);
+      }
+
+      let body = armored_body(signature, PGP_HEADER, PGP_FOOTER)
+        .or_else(|| armored_body(signature, SSH_HEADER, SSH_FOOTER))
+        .unwrap();
+      for i in 0..body.len() {
+        let _ = pgp_key_id(&body[..i]);
+        let _ = ssh_key(&body[..i]);
+      }
+    }
+  }
+
+  #[test]
+  fn corrupted_signatures_dont_panic() {
+    for signature in [PGP_SIGNATURE, SSH_SIGNATURE, &key_id_only_signature()] {
+      let body = armored_body(signature, PGP_HEADER, PGP_FOOTER)
+        .or_else(|| armored_body(signature, SSH_HEADER, SSH_FOOTER))
+        .unwrap();
+
+      for i in 0..body.len() {
+        for byte in [0x00, 0x7f, 0x80, 0xbf, 0xc0, 0xdf, 0xff] {
+          let mut body = body.clone();
+          body[i] = byte;
+          let _ = pgp_key_id(&body);
+          let _ = ssh_key(&body);
+        }
+      }
+    }
+  }
+}
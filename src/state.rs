@@ -0,0 +1,223 @@
+//! The state of the last render of a repository
+//!
+//! We keep track of the commit each branch of a repository pointed to the
+//! last time its pages were rendered in a `.yagit-state` file in its output
+//! directory, so that incremental builds only re-render the pages of the
+//! files which changed since then. Each line of the file holds the id of a
+//! commit followed by the name of its branch.
+//!
+//! We also cache the files changed by each commit, which are needed for the
+//! last commits listed in the tree pages: so that the diffs of commits whose
+//! pages are up to date don't have to be computed again. These are listed
+//! after the branches, as a line holding the id of the commit followed by a
+//! line for each of its changed files, indented by a tab.
+//!
+//! The header of the pages of a branch lists the docs and the other branches
+//! of the repository, so the pages of unchanged blobs still need to be
+//! rendered again if any of these changed. We keep a hash of the inputs of
+//! the header of each branch in lines starting with `header`, followed by
+//! the hash and the name of the branch.
+
+use std::{
+  collections::HashMap,
+  io::{self, Write},
+  path::{Path, PathBuf},
+  fs,
+};
+use git2::Oid;
+
+/// The name of the state file, relative to the output directory of the
+/// repository
+pub const STATE_FILE: &str = ".yagit-state";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct State {
+  heads:         HashMap<String, Oid>,
+  headers:       HashMap<String, String>,
+  changed_paths: HashMap<Oid, Vec<PathBuf>>,
+}
+
+impl State {
+  /// Reads the state file at `path`
+  ///
+  /// Returns an empty state if there is no such file, or if it is invalid: in
+  /// which case all pages are rendered again.
+  pub fn read<P: AsRef<Path>>(path: P) -> Self {
+    let path = path.as_ref();
+    let Ok(content) = fs::read_to_string(path) else {
+      return Self::default();
+    };
+
+    match Self::parse(&content) {
+      Some(state) => state,
+      None => {
+        warnln!("Invalid state file {path:?}. Rendering all pages again");
+        Self::default()
+      }
+    }
+  }
+
+  fn parse(content: &str) -> Option<Self> {
+    let mut heads = HashMap::new();
+    let mut headers = HashMap::new();
+    let mut changed_paths = HashMap::new();
+    let mut commit: Option<&mut Vec<PathBuf>> = None;
+
+    for line in content.lines() {
+      if let Some(path) = line.strip_prefix('\t') {
+        commit.as_mut()?.push(PathBuf::from(path));
+      } else if let Some(header) = line.strip_prefix("header ") {
+        let (hash, branch) = header.split_once(' ')?;
+        headers.insert(String::from(branch), String::from(hash));
+      } else if let Some((id, branch)) = line.split_once(' ') {
+        heads.insert(String::from(branch), Oid::from_str(id).ok()?);
+      } else {
+        let id = Oid::from_str(line).ok()?;
+        commit = Some(changed_paths.entry(id).or_default());
+      }
+    }
+
+    Some(Self { heads, headers, changed_paths })
+  }
+
+  /// The commit `branch` pointed to when it was last rendered
+  pub fn head(&self, branch: &str) -> Option<Oid> {
+    self.heads.get(branch).copied()
+  }
+
+  /// The branches which were rendered, along with the commit each of them
+  /// pointed to
+  pub fn heads(&self) -> impl Iterator<Item = (&str, Oid)> {
+    self.heads.iter().map(|(branch, id)| (branch.as_str(), *id))
+  }
+
+  pub fn set_head(&mut self, branch: &str, id: Oid) {
+    self.heads.insert(String::from(branch), id);
+  }
+
+  /// The hash of the inputs of the header of the pages of `branch` when it
+  /// was last rendered
+  pub fn header(&self, branch: &str) -> Option<&str> {
+    self.headers.get(branch).map(String::as_str)
+  }
+
+  pub fn set_header(&mut self, branch: &str, hash: String) {
+    self.headers.insert(String::from(branch), hash);
+  }
+
+  /// The files changed by commit `id`, if they were cached
+  pub fn changed_paths(&self, id: Oid) -> Option<&[PathBuf]> {
+    self.changed_paths.get(&id).map(Vec::as_slice)
+  }
+
+  /// Caches the files changed by commit `id`
+  ///
+  /// Paths which are not valid UTF-8 or which contain a newline can't be
+  /// written to the state file, in which case nothing is cached for the
+  /// commit.
+  pub fn set_changed_paths(&mut self, id: Oid, paths: Vec<PathBuf>) {
+    let representable = paths
+      .iter()
+      .all(|path| path.to_str().is_some_and(|path| !path.contains('\n')));
+
+    if representable {
+      self.changed_paths.insert(id, paths);
+    }
+  }
+
+  pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+    // sort the branches so that the file doesn't change between runs
+    let mut heads: Vec<_> = self.heads.iter().collect();
+    heads.sort();
+
+    for (branch, id) in heads {
+      writeln!(w, "{id} {branch}")?;
+    }
+
+    let mut headers: Vec<_> = self.headers.iter().collect();
+    headers.sort();
+
+    for (branch, hash) in headers {
+      writeln!(w, "header {hash} {branch}")?;
+    }
+
+    let mut commits: Vec<_> = self.changed_paths.iter().collect();
+    commits.sort();
+
+    for (id, paths) in commits {
+      writeln!(w, "{id}")?;
+      for path in paths {
+        writeln!(w, "\t{}", path.display())?;
+      }
+    }
+
+    w.flush()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let mut state = State::default();
+    state.set_head("master", Oid::from_str("5e1111507cab4e1b09d11c4e6cac39f0d9784d80").unwrap());
+    state.set_head("feature/x", Oid::from_str("853c55a871b81bfec7c0c7c7b71ca23a741722e2").unwrap());
+
+    let mut content = Vec::new();
+    state.write(&mut content).unwrap();
+    let content = String::from_utf8(content).unwrap();
+
+    assert_eq!(
+      content,
+      "853c55a871b81bfec7c0c7c7b71ca23a741722e2 feature/x\n5e1111507cab4e1b09d11c4e6cac39f0d9784d80 master\n",
+    );
+    assert_eq!(State::parse(&content), Some(state));
+    assert_eq!(State::parse("not-an-id master\n"), None);
+  }
+
+  #[test]
+  fn headers_round_trip() {
+    let mut state = State::default();
+    state.set_head("master", Oid::from_str("5e1111507cab4e1b09d11c4e6cac39f0d9784d80").unwrap());
+    state.set_header("master", String::from("abc123"));
+    state.set_header("feature/x", String::from("def456"));
+
+    let mut content = Vec::new();
+    state.write(&mut content).unwrap();
+    let parsed = State::parse(&String::from_utf8(content).unwrap()).unwrap();
+
+    assert_eq!(parsed, state);
+    assert_eq!(parsed.header("master"), Some("abc123"));
+    assert_eq!(parsed.header("feature/x"), Some("def456"));
+    assert_eq!(parsed.header("next"), None);
+    assert_eq!(State::parse("header abc123\n"), None);
+  }
+
+  #[test]
+  fn changed_paths_round_trip() {
+    let id = Oid::from_str("5e1111507cab4e1b09d11c4e6cac39f0d9784d80").unwrap();
+    let empty = Oid::from_str("853c55a871b81bfec7c0c7c7b71ca23a741722e2").unwrap();
+    let unrepresentable = Oid::from_str("7f115318a614443c3a8db047a33619223971974e").unwrap();
+
+    let mut state = State::default();
+    state.set_head("master", id);
+    state.set_changed_paths(id, vec![PathBuf::from("src/main.rs"), PathBuf::from("a b")]);
+    state.set_changed_paths(empty, Vec::new());
+    state.set_changed_paths(unrepresentable, vec![PathBuf::from("a\nb")]);
+
+    let mut content = Vec::new();
+    state.write(&mut content).unwrap();
+    let parsed = State::parse(&String::from_utf8(content).unwrap()).unwrap();
+
+    assert_eq!(parsed, state);
+    assert_eq!(
+      parsed.changed_paths(id),
+      Some(&[PathBuf::from("src/main.rs"), PathBuf::from("a b")][..]),
+    );
+    assert_eq!(parsed.changed_paths(empty), Some(&[][..]));
+    assert_eq!(parsed.changed_paths(unrepresentable), None);
+    assert_eq!(State::parse("\tsrc/main.rs\n"), None);
+  }
+}
@@ -1,21 +1,68 @@
-#![allow(clippy::borrow_interior_mutable_const, clippy::declare_interior_mutable_const)]
-use std::{fmt::{self, Display}, mem, ffi::{CStr, CString}, sync::LazyLock};
+use std::{
+  fmt::{self, Display},
+  mem,
+  ffi::{CStr, CString},
+  sync::OnceLock,
+  time::SystemTime,
+};
 use libc::{self, time_t, c_char};
 use git2::Time;
 
+use crate::config::{self, TimeZone};
+
 const MINUTES_IN_AN_HOUR: u64 = 60;
 
-const DATE_TIME_FMT: LazyLock<CString> = LazyLock::new(
-  || CString::new("%Y-%m-%d %H:%M").unwrap()
-);
+const SECS_IN_A_MINUTE: u64 = 60;
+const SECS_IN_AN_HOUR:  u64 = 60 * SECS_IN_A_MINUTE;
+const SECS_IN_A_DAY:    u64 = 24 * SECS_IN_AN_HOUR;
+const SECS_IN_A_MONTH:  u64 = 30 * SECS_IN_A_DAY;
+const SECS_IN_A_YEAR:   u64 = 365 * SECS_IN_A_DAY;
+
+const DEFAULT_DATE_TIME_FMT: &str = "%Y-%m-%d %H:%M";
+const DEFAULT_DATE_FMT:      &str = "%d/%m/%Y %H:%M";
+const DEFAULT_FULL_DATE_FMT: &str = "%a, %d %b %Y %H:%M:%S";
+
+static FORMATS: OnceLock<Formats> = OnceLock::new();
+
+struct Formats {
+  date_time: CString,
+  date:      CString,
+  full_date: CString,
+}
+
+impl Formats {
+  fn load() -> Self {
+    Self {
+      date_time: load_fmt("time.datetime_fmt", config::DATE_TIME_FMT, DEFAULT_DATE_TIME_FMT),
+      date:      load_fmt("time.date_fmt", config::DATE_FMT, DEFAULT_DATE_FMT),
+      full_date: load_fmt("time.full_date_fmt", config::FULL_DATE_FMT, DEFAULT_FULL_DATE_FMT),
+    }
+  }
 
-const DATE_FMT: LazyLock<CString> = LazyLock::new(
-  || CString::new("%d/%m/%Y %H:%M").unwrap()
-);
+  fn get() -> &'static Self {
+    FORMATS.get_or_init(Self::load)
+  }
+}
 
-const FULL_DATE_FMT: LazyLock<CString> = LazyLock::new(
-  || CString::new("%a, %d %b %Y %H:%M:%S").unwrap()
-);
+fn load_fmt(key: &str, fmt: &str, default: &str) -> CString {
+  if fmt.is_empty() {
+    warnln!("{key} is empty. Using the default format {default:?}");
+    return CString::new(default).unwrap();
+  }
+
+  match CString::new(fmt) {
+    Ok(fmt) => fmt,
+    Err(_)  => {
+      warnln!("{key} contains a NUL character. Using the default format {default:?}");
+      CString::new(default).unwrap()
+    }
+  }
+}
+
+/// Validates the time formats in the configuration, warning about invalid ones
+pub fn init() {
+  Formats::get();
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct DateTime(pub Time);
@@ -26,49 +73,129 @@ pub struct Date(pub Time);
 #[derive(Clone, Copy, Debug)]
 pub struct FullDate(pub Time);
 
+/// A timestamp in the RFC 3339 format, in UTC, as used by Atom feeds
+#[derive(Clone, Copy, Debug)]
+pub struct Rfc3339(pub Time);
+
+/// The time elapsed since a given time, e.g. "3 days ago"
+#[derive(Clone, Copy, Debug)]
+pub struct RelativeTime(pub Time);
+
 const FTIME_BUFF_LEN:  usize = 64;
-// TODO: [safety]: make this thread-safe?
-// the application is currently single-threaded, so this is a non-issue for now
-static mut FTIME_BUFF: [c_char; FTIME_BUFF_LEN] = [0; FTIME_BUFF_LEN];
 
-#[allow(static_mut_refs)]
+/// Breaks `time` down according to [`config::TIME_ZONE`], returning the UTC
+/// offset of the broken-down time in minutes
+fn localtime(time: &Time) -> (libc::tm, i64) {
+  let secs = time.seconds();
+
+  unsafe {
+    let mut tm: libc::tm = mem::zeroed();
+    match config::TIME_ZONE {
+      TimeZone::Local => {
+        libc::localtime_r(&(secs as time_t), &mut tm);
+        let offset = tm.tm_gmtoff as i64 / SECS_IN_A_MINUTE as i64;
+        (tm, offset)
+      }
+      TimeZone::Utc => {
+        libc::gmtime_r(&(secs as time_t), &mut tm);
+        (tm, 0)
+      }
+      TimeZone::Commit => {
+        let offset = time.offset_minutes() as i64;
+        let secs = secs + offset * SECS_IN_A_MINUTE as i64;
+        libc::gmtime_r(&(secs as time_t), &mut tm);
+        (tm, offset)
+      }
+    }
+  }
+}
+
 fn strftime(
-  fmt: &CString,
-  time: &Time,
+  fmt: &CStr,
+  tm: &libc::tm,
   f: &mut fmt::Formatter<'_>
 ) -> fmt::Result {
-  let time = time.seconds() as time_t;
+  let mut buff: [c_char; FTIME_BUFF_LEN] = [0; FTIME_BUFF_LEN];
 
   unsafe {
-    let mut tm = mem::zeroed();
-    libc::localtime_r(&time, &mut tm);
-
-    libc::strftime(FTIME_BUFF.as_mut_ptr(), FTIME_BUFF_LEN, fmt.as_ptr(), &tm);
-    FTIME_BUFF[FTIME_BUFF_LEN - 1] = 0; // prevent buffer overflows when
-                                        // converting back to a CStr
-    write!(f, "{}", CStr::from_ptr(FTIME_BUFF.as_ptr()).to_str().unwrap())
+    libc::strftime(buff.as_mut_ptr(), FTIME_BUFF_LEN, fmt.as_ptr(), tm);
   }
+  buff[FTIME_BUFF_LEN - 1] = 0; // prevent buffer overflows when converting
+                                // back to a CStr
+
+  // buff outlives the CStr, which borrows from it
+  let s = unsafe { CStr::from_ptr(buff.as_ptr()) };
+  write!(f, "{}", s.to_string_lossy())
 }
 
 impl Display for DateTime {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    strftime(&DATE_TIME_FMT, &self.0, f)
+    let (tm, _) = localtime(&self.0);
+    strftime(&Formats::get().date_time, &tm, f)
   }
 }
 
 impl Display for Date {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    strftime(&DATE_FMT, &self.0, f)
+    let (tm, _) = localtime(&self.0);
+    strftime(&Formats::get().date, &tm, f)
   }
 }
 
 impl Display for FullDate {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    let timezone_sign = self.0.sign();
-    let timezone_mins = self.0.offset_minutes().unsigned_abs() as u64;
-    let timezone = timezone_mins / MINUTES_IN_AN_HOUR;
+    let (tm, offset) = localtime(&self.0);
+    let timezone_sign = if offset < 0 { '-' } else { '+' };
+    let timezone_mins = offset.unsigned_abs();
+    let timezone_hours = timezone_mins / MINUTES_IN_AN_HOUR;
+    let timezone_mins = timezone_mins % MINUTES_IN_AN_HOUR;
+
+    strftime(&Formats::get().full_date, &tm, f)?;
+    write!(f, " {timezone_sign}{timezone_hours:02}{timezone_mins:02}")
+  }
+}
+
+impl Display for Rfc3339 {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // unlike the other formats, this doesn't depend on time.zone
+    let tm = unsafe {
+      let mut tm: libc::tm = mem::zeroed();
+      libc::gmtime_r(&(self.0.seconds() as time_t), &mut tm);
+      tm
+    };
+
+    strftime(c"%Y-%m-%dT%H:%M:%SZ", &tm, f)
+  }
+}
+
+impl Display for RelativeTime {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let now = SystemTime::now()
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .unwrap()
+      .as_secs() as i64;
+    // commits from the future (e.g. because of clock skew) are shown as
+    // having just happened
+    let delta = (now - self.0.seconds()).max(0) as u64;
+
+    let (n, unit) = if delta >= SECS_IN_A_YEAR {
+      (delta / SECS_IN_A_YEAR, "year")
+    } else if delta >= SECS_IN_A_MONTH {
+      (delta / SECS_IN_A_MONTH, "month")
+    } else if delta >= SECS_IN_A_DAY {
+      (delta / SECS_IN_A_DAY, "day")
+    } else if delta >= SECS_IN_AN_HOUR {
+      (delta / SECS_IN_AN_HOUR, "hour")
+    } else if delta >= SECS_IN_A_MINUTE {
+      (delta / SECS_IN_A_MINUTE, "minute")
+    } else {
+      return write!(f, "just now");
+    };
 
-    strftime(&FULL_DATE_FMT, &self.0, f)?;
-    write!(f, " {timezone_sign}{timezone:04}")
+    if n == 1 {
+      write!(f, "1 {unit} ago")
+    } else {
+      write!(f, "{n} {unit}s ago")
+    }
   }
 }
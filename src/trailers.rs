@@ -0,0 +1,100 @@
+//! Parsing of commit message trailers
+//!
+//! Trailers are the `Key: value` lines at the end of a commit message, such as
+//! `Signed-off-by: Jane Doe <jane@example.com>`. Like `git interpret-trailers`,
+//! we only consider the last paragraph of the message, and only if all of its
+//! lines are trailers or continuations of the previous trailer.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trailer<'a> {
+  pub key:   &'a str,
+  /// The value of the trailer, with its continuation lines joined by spaces
+  pub value: String,
+}
+
+/// Splits the trailers off the end of the body of a commit message, returning
+/// the rest of the body and the trailers
+///
+/// Returns an empty list of trailers if the last paragraph of `body` isn't
+/// made up of trailers only.
+pub fn split(body: &str) -> (&str, Vec<Trailer<'_>>) {
+  let body = body.trim_end();
+  let (rest, last) = match body.rsplit_once("\n\n") {
+    Some((rest, last)) => (rest, last),
+    None               => ("", body),
+  };
+
+  let mut trailers: Vec<Trailer<'_>> = Vec::new();
+  for line in last.lines() {
+    if line.starts_with([' ', '\t']) {
+      match trailers.last_mut() {
+        Some(trailer) => {
+          trailer.value.push(' ');
+          trailer.value.push_str(line.trim());
+        }
+        None => return (body, Vec::new()),
+      }
+    } else if let Some((key, value)) = parse_line(line) {
+      trailers.push(Trailer { key, value: String::from(value) });
+    } else {
+      return (body, Vec::new());
+    }
+  }
+
+  (rest.trim_end(), trailers)
+}
+
+/// Parses a line such as `Co-authored-by: Jane Doe <jane@example.com>`
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+  let (key, value) = line.split_once(':')?;
+  let is_key = !key.is_empty()
+    && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+
+  if is_key && !value.trim().is_empty() {
+    Some((key, value.trim()))
+  } else {
+    None
+  }
+}
+
+/// Splits a value such as `Jane Doe <jane@example.com>` into a name and an
+/// email address
+pub fn split_email(value: &str) -> Option<(&str, &str)> {
+  let (name, email) = value.strip_suffix('>')?.rsplit_once('<')?;
+  if email.contains('@') && !email.contains(char::is_whitespace) {
+    Some((name.trim(), email))
+  } else {
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn splits_trailing_trailers() {
+    let body = "Some explanation.\n\nFixes: #12\nSigned-off-by: Jane Doe <jane@example.com>\nReviewed-by: John\n  Doe\n";
+    let (rest, trailers) = split(body);
+
+    assert_eq!(rest, "Some explanation.");
+    assert_eq!(trailers, [
+      Trailer { key: "Fixes", value: String::from("#12") },
+      Trailer { key: "Signed-off-by", value: String::from("Jane Doe <jane@example.com>") },
+      Trailer { key: "Reviewed-by", value: String::from("John Doe") },
+    ]);
+    assert_eq!(
+      split_email(&trailers[1].value),
+      Some(("Jane Doe", "jane@example.com")),
+    );
+  }
+
+  #[test]
+  fn ignores_paragraphs_with_other_lines() {
+    let body = "Some explanation.\n\nNote: this is a sentence\nwhich goes on.";
+    assert_eq!(split(body), (body, Vec::new()));
+
+    let body = "Key with spaces: value";
+    assert_eq!(split(body), (body, Vec::new()));
+  }
+}
@@ -0,0 +1,482 @@
+//! Renders a scratch repository and checks the invariants of the output
+
+use std::{
+  env,
+  fs,
+  path::{Path, PathBuf},
+};
+use git2::{BranchType, Oid, Repository, Signature};
+
+use yagit::{
+  command::Flags,
+  config::{Paths, TREE_SUBDIR, BLOB_SUBDIR, COMMIT_SUBDIR},
+  log::Stats,
+  RepoInfo,
+  RepoRenderer,
+};
+
+const REPO_NAME: &str = "scratch";
+
+/// A temporary directory holding a store and an output directory, removed
+/// when dropped
+struct Site {
+  root:  PathBuf,
+  paths: Paths,
+}
+
+impl Site {
+  fn new(name: &str) -> Self {
+    let mut root = env::temp_dir();
+    root.push(format!("yagit-render-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    let paths = Paths {
+      output:        root.join("site"),
+      store:         root.join("public"),
+      private_store: root.join("private"),
+    };
+    fs::create_dir_all(&paths.output).unwrap();
+    fs::create_dir_all(&paths.store).unwrap();
+    fs::create_dir_all(&paths.private_store).unwrap();
+
+    Self { root, paths, }
+  }
+
+  fn repo_path(&self) -> PathBuf {
+    self.paths.store.join(REPO_NAME)
+  }
+
+  fn output(&self, page: &str) -> PathBuf {
+    self.paths.output.join(REPO_NAME).join(page)
+  }
+
+  /// Renders the scratch repository and the index of projects
+  fn render(&self) {
+    self.render_branch(None);
+  }
+
+  /// Renders `branch` of the scratch repository, or its `HEAD`, and the index
+  /// of projects
+  fn render_branch(&self, branch: Option<&str>) {
+    let repo = RepoInfo::open(self.repo_path(), REPO_NAME, false).unwrap();
+    let stats = Stats::default();
+
+    RepoRenderer::new(&repo, &self.paths, Flags::EMPTY, branch, 2, &stats)
+      .unwrap()
+      .render()
+      .unwrap();
+
+    yagit::render_index(&self.paths, &[repo], Flags::EMPTY).unwrap();
+  }
+}
+
+impl Drop for Site {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.root);
+  }
+}
+
+/// Writes `files` to the work tree of `repo` and commits all of them
+fn commit(repo: &Repository, files: &[(&str, &[u8])], message: &str) -> Oid {
+  let workdir = repo.workdir().unwrap();
+  let mut index = repo.index().unwrap();
+
+  for (path, content) in files {
+    let full_path = workdir.join(path);
+    fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+    fs::write(&full_path, content).unwrap();
+    index.add_path(Path::new(path)).unwrap();
+  }
+  index.write().unwrap();
+
+  let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+  let sig = Signature::now("Jane Doe", "jane@example.com").unwrap();
+  let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+  let parents: Vec<_> = parent.iter().collect();
+
+  repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents).unwrap()
+}
+
+/// Creates the scratch repository, returning the ids of its commits
+fn scratch_repo(site: &Site) -> [Oid; 2] {
+  let repo = Repository::init(site.repo_path()).unwrap();
+
+  let first = commit(&repo, &[
+    ("README.md", b"# Scratch\n\nA **scratch** repository.\n"),
+    ("LICENSE",   b"Do whatever you want.\n"),
+  ], "Initial commit");
+
+  let second = commit(&repo, &[
+    ("src/main.c", b"int main(void) {\n\treturn 0;\n}\n"),
+    ("data.bin",   b"\x00\x01\x02\xff\xfe\x00"),
+  ], "Add some <sources> & data\n\nSigned-off-by: Jane Doe <jane@example.com>");
+
+  [first, second]
+}
+
+/// All files with the `.html` extension in `dir`, recursively
+fn html_files(dir: &Path) -> Vec<PathBuf> {
+  let mut files = Vec::new();
+  for entry in fs::read_dir(dir).unwrap() {
+    let path = entry.unwrap().path();
+    if path.is_dir() {
+      files.extend(html_files(&path));
+    } else if path.extension().is_some_and(|ext| ext == "html") {
+      files.push(path);
+    }
+  }
+  files
+}
+
+/// Checks that every tag in `html` is closed, and closed in the right order
+///
+/// This is far from a complete HTML parser: it's only meant to catch
+/// malformed markup in the pages we generate, which always close their
+/// non-void elements explicitly.
+fn check_well_formed(html: &str) -> Result<(), String> {
+  const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link",
+    "meta", "source", "track", "wbr",
+  ];
+
+  let mut stack: Vec<String> = Vec::new();
+  let mut rest = html;
+
+  while let Some(start) = rest.find('<') {
+    rest = &rest[start..];
+    let line = html[..html.len() - rest.len()].lines().count();
+
+    if let Some(comment) = rest.strip_prefix("<!--") {
+      let end = comment.find("-->").ok_or("unterminated comment")?;
+      rest = &comment[end + 3..];
+      continue;
+    }
+    if rest.starts_with("<!") {
+      let end = rest.find('>').ok_or("unterminated doctype")?;
+      rest = &rest[end + 1..];
+      continue;
+    }
+
+    // find the end of the tag, skipping over quoted attribute values
+    let mut quote = None;
+    let end = rest
+      .char_indices()
+      .skip(1)
+      .find(|&(_, c)| match quote {
+        Some(q) => { if c == q { quote = None; } false }
+        None if c == '"' || c == '\'' => { quote = Some(c); false }
+        None => c == '>',
+      })
+      .map(|(i, _)| i)
+      .ok_or_else(|| format!("unterminated tag at line {line}"))?;
+    let tag = &rest[1..end];
+    rest = &rest[end + 1..];
+
+    if let Some(name) = tag.strip_prefix('/') {
+      let name = name.trim().to_ascii_lowercase();
+      match stack.pop() {
+        Some(open) if open == name => {}
+        Some(open) => {
+          return Err(format!("</{name}> closes <{open}> at line {line}"));
+        }
+        None => return Err(format!("unmatched </{name}> at line {line}")),
+      }
+      continue;
+    }
+
+    let name: String = tag
+      .chars()
+      .take_while(|c| c.is_ascii_alphanumeric())
+      .collect::<String>()
+      .to_ascii_lowercase();
+    if name.is_empty() {
+      return Err(format!("unescaped < at line {line}"));
+    }
+    if tag.ends_with('/') || VOID_ELEMENTS.contains(&name.as_str()) {
+      continue;
+    }
+
+    // the contents of scripts are not markup
+    if name == "script" {
+      let end = rest.find("</script>").ok_or("unterminated script")?;
+      rest = &rest[end + "</script>".len()..];
+      continue;
+    }
+
+    stack.push(name);
+  }
+
+  match stack.pop() {
+    Some(open) => Err(format!("unclosed <{open}>")),
+    None       => Ok(()),
+  }
+}
+
+/// The targets of the absolute links in `html`, without their fragments
+fn local_links(html: &str) -> Vec<&str> {
+  html
+    .split("href=\"")
+    .skip(1)
+    .filter_map(|s| s.split('"').next())
+    .filter(|link| link.starts_with('/'))
+    .map(|link| link.split('#').next().unwrap())
+    .collect()
+}
+
+#[test]
+fn renders_all_pages() {
+  let site = Site::new("pages");
+  let [first, second] = scratch_repo(&site);
+  site.render();
+
+  let pages = [
+    String::from("index.html"),
+    String::from("license.html"),
+    format!("{COMMIT_SUBDIR}/index.html"),
+    format!("{COMMIT_SUBDIR}/{first}.html"),
+    format!("{COMMIT_SUBDIR}/{second}.html"),
+    format!("{TREE_SUBDIR}/index.html"),
+    format!("{TREE_SUBDIR}/README.md.html"),
+    format!("{TREE_SUBDIR}/data.bin.html"),
+    format!("{TREE_SUBDIR}/src/index.html"),
+    format!("{TREE_SUBDIR}/src/main.c.html"),
+    format!("{BLOB_SUBDIR}/data.bin"),
+    format!("{BLOB_SUBDIR}/src/main.c"),
+  ];
+  for page in pages {
+    assert!(site.output(&page).is_file(), "{page} was not rendered");
+  }
+}
+
+#[test]
+fn commit_page_links_resolve() {
+  let site = Site::new("links");
+  let [first, second] = scratch_repo(&site);
+  site.render();
+
+  for id in [first, second] {
+    let page = site.output(&format!("{COMMIT_SUBDIR}/{id}.html"));
+    let html = fs::read_to_string(&page).unwrap();
+
+    let links = local_links(&html);
+    assert!(!links.is_empty());
+
+    for link in links {
+      let mut target = site.paths.output.join(link.trim_start_matches('/'));
+      if link.ends_with('/') {
+        target.push("index.html");
+      }
+      assert!(target.is_file(), "broken link to {link:?} in {page:?}");
+    }
+  }
+}
+
+#[test]
+fn commit_page_links_resolve_after_removing_files() {
+  let site = Site::new("removed-links");
+  let [_, second] = scratch_repo(&site);
+  site.render();
+
+  let page = site.output(&format!("{COMMIT_SUBDIR}/{second}.html"));
+  assert!(fs::read_to_string(&page).unwrap().contains("main.c.html"));
+
+  let repo = Repository::open(site.repo_path()).unwrap();
+  let mut index = repo.index().unwrap();
+  index.remove_path(Path::new("src/main.c")).unwrap();
+  let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+  let sig = Signature::now("Jane Doe", "jane@example.com").unwrap();
+  let parent = repo.find_commit(second).unwrap();
+  repo.commit(Some("HEAD"), &sig, &sig, "Remove the sources", &tree, &[&parent]).unwrap();
+  site.render();
+
+  let html = fs::read_to_string(&page).unwrap();
+  for link in local_links(&html) {
+    let mut target = site.paths.output.join(link.trim_start_matches('/'));
+    if link.ends_with('/') {
+      target.push("index.html");
+    }
+    assert!(target.is_file(), "broken link to {link:?} in {page:?}");
+  }
+}
+
+#[test]
+fn readme_is_rendered_as_html() {
+  let site = Site::new("readme");
+  scratch_repo(&site);
+  site.render();
+
+  let html = fs::read_to_string(site.output("index.html")).unwrap();
+  assert!(html.contains("<strong>scratch</strong>"));
+  assert!(!html.contains("**scratch**"));
+}
+
+#[test]
+fn pages_are_well_formed() {
+  let site = Site::new("well-formed");
+  scratch_repo(&site);
+  site.render();
+
+  let pages = html_files(&site.paths.output);
+  assert!(!pages.is_empty());
+
+  for page in pages {
+    let html = fs::read_to_string(&page).unwrap();
+    if let Err(e) = check_well_formed(&html) {
+      panic!("{page:?} is malformed: {e}");
+    }
+  }
+}
+
+#[test]
+fn deleted_branches_are_removed() {
+  let site = Site::new("branches");
+  let [first, _] = scratch_repo(&site);
+
+  let repo = Repository::open(site.repo_path()).unwrap();
+  repo.branch("feature/x", &repo.find_commit(first).unwrap(), false).unwrap();
+  site.render();
+  assert!(site.output("branches/feature/x/tree/index.html").is_file());
+
+  repo.find_branch("feature/x", BranchType::Local).unwrap().delete().unwrap();
+  site.render();
+  assert!(!site.output("branches/feature").exists());
+
+  let state = fs::read_to_string(site.output(".yagit-state")).unwrap();
+  assert!(!state.contains("feature/x"));
+}
+
+#[test]
+fn repo_config_overrides_are_escaped() {
+  let site = Site::new("repo-config");
+  scratch_repo(&site);
+
+  let repo = Repository::open(site.repo_path()).unwrap();
+  commit(&repo, &[
+    (".yagit.toml", b"description = \"<script>alert(1)</script>\"\nclone_url = \"javascript:alert(1)\"\n"),
+  ], "Add a configuration file");
+  site.render();
+
+  let html = fs::read_to_string(site.output("index.html")).unwrap();
+  assert!(!html.contains("javascript:"), "clone_url override was not rejected");
+
+  for page in [site.paths.output.join("index.html"), site.output("index.html")] {
+    let html = fs::read_to_string(&page).unwrap();
+    assert!(!html.contains("<script>alert(1)"), "unescaped description in {page:?}");
+    assert!(html.contains("&lt;script&gt;alert(1)"), "missing description in {page:?}");
+  }
+}
+
+#[test]
+fn unchanged_trees_list_new_branches() {
+  let site = Site::new("tree-header");
+  let [_, second] = scratch_repo(&site);
+  site.render();
+
+  let repo = Repository::open(site.repo_path()).unwrap();
+  repo.branch("next", &repo.find_commit(second).unwrap(), false).unwrap();
+  site.render();
+
+  let html = fs::read_to_string(site.output(&format!("{TREE_SUBDIR}/src/index.html"))).unwrap();
+  assert!(html.contains("branches/next/"));
+}
+
+#[test]
+fn unchanged_blobs_follow_branch_changes() {
+  let site = Site::new("blob-header");
+  let [_, second] = scratch_repo(&site);
+  site.render();
+
+  let page = site.output(&format!("{TREE_SUBDIR}/src/main.c.html"));
+  let repo = Repository::open(site.repo_path()).unwrap();
+  repo.branch("next", &repo.find_commit(second).unwrap(), false).unwrap();
+  site.render();
+  assert!(fs::read_to_string(&page).unwrap().contains("branches/next/"));
+
+  repo.find_branch("next", BranchType::Local).unwrap().delete().unwrap();
+  site.render();
+  assert!(!fs::read_to_string(&page).unwrap().contains("branches/next/"));
+}
+
+#[test]
+fn global_feed_follows_the_rendered_branch() {
+  let site = Site::new("feed");
+  let [first, _] = scratch_repo(&site);
+
+  let repo = Repository::open(site.repo_path()).unwrap();
+  let first = repo.find_commit(first).unwrap();
+  let sig = Signature::now("Jane Doe", "jane@example.com").unwrap();
+  let tree = first.tree().unwrap();
+  repo.commit(Some("refs/heads/next"), &sig, &sig, "Only on next", &tree, &[&first]).unwrap();
+
+  site.render_branch(Some("next"));
+  let feed = fs::read_to_string(site.paths.output.join("feed.xml")).unwrap();
+  assert!(feed.contains("Only on next"));
+  assert!(!feed.contains("Add some"));
+
+  site.render();
+  let feed = fs::read_to_string(site.paths.output.join("feed.xml")).unwrap();
+  assert!(!feed.contains("Only on next"));
+  assert!(feed.contains("Add some"));
+}
+
+#[test]
+fn branch_switcher_links_resolve() {
+  let site = Site::new("branch-names");
+  let [first, _] = scratch_repo(&site);
+
+  let repo = Repository::open(site.repo_path()).unwrap();
+  repo.branch("fix#1+2", &repo.find_commit(first).unwrap(), false).unwrap();
+  site.render();
+
+  let html = fs::read_to_string(site.output("index.html")).unwrap();
+  let links: Vec<_> = local_links(&html)
+    .into_iter()
+    .filter(|link| link.contains("/branches/"))
+    .collect();
+  assert_eq!(links.len(), 1);
+
+  for link in links {
+    let path = link.trim_start_matches('/').replace("%23", "#").replace("%2B", "+");
+    assert!(site.paths.output.join(path).is_file(), "broken link to {link:?}");
+  }
+}
+
+#[test]
+fn search_index_url_is_encoded() {
+  let site = Site::new("search-url");
+  let [first, _] = scratch_repo(&site);
+
+  let repo = Repository::open(site.repo_path()).unwrap();
+  repo.branch("fix#1+2", &repo.find_commit(first).unwrap(), false).unwrap();
+  site.render();
+
+  let log = site.output(&format!("branches/fix#1+2/{COMMIT_SUBDIR}/index.html"));
+  let html = fs::read_to_string(log).unwrap();
+  assert!(html.contains("/branches/fix%231%2B2/search.json\""));
+  assert!(site.output("branches/fix#1+2/search.json").is_file());
+}
+
+#[test]
+fn search_index_marks_commits_with_pages() {
+  let site = Site::new("search-index");
+  let [first, second] = scratch_repo(&site);
+  site.render();
+
+  let index = fs::read_to_string(site.output("search.json")).unwrap();
+  for id in [first, second] {
+    let has_page = site.output(&format!("{COMMIT_SUBDIR}/{id}.html")).is_file();
+    let entry = index.split("},").find(|entry| entry.contains(&id.to_string())).unwrap();
+    assert!(entry.contains(&format!("\"page\":{has_page}")));
+  }
+
+  let search = fs::read_to_string(site.paths.output.join("search.html")).unwrap();
+  assert!(search.contains(&format!("c.id.slice(0, {})", yagit::config::SHORT_ID_LEN)));
+}
+
+#[test]
+fn well_formedness_check_catches_malformed_tags() {
+  assert!(check_well_formed("<dl><dd><a href=\"a>b\">x</a></dd></dl>").is_ok());
+  assert!(check_well_formed("<dd><a href=\"x\">x</a><dd>").is_err());
+  assert!(check_well_formed("<p>a <x> b</p>").is_err());
+  assert!(check_well_formed("<p>a < b</p>").is_err());
+}